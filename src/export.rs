@@ -0,0 +1,511 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+use log::{error, info};
+use serde_derive::{Deserialize, Serialize};
+use winsafe::co::{BS, ES};
+use winsafe::gui::{CheckState, Horz, Vert};
+use winsafe::msg::wm::Close;
+use winsafe::{gui, prelude::*};
+
+use crate::highlighter::{HighlightMatch, LineStyle, TextStyle};
+use crate::lineview::LineBasedFileView;
+use crate::log_reader::{log_len, open_log};
+use crate::search::CompressedSearchResults;
+
+/// What to write next to the matching lines when exporting search results.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct ExportOptions {
+    pub line_numbers: bool,
+    pub context_lines: u64,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            line_numbers: true,
+            context_lines: 0,
+        }
+    }
+}
+
+/// Streams every line in `files` that is a search hit (plus `context_lines` around it) to
+/// `target`, in the format of grep: `12:hit`, `13-context` and `--` between groups.
+///
+/// `progress` is called with the number of bytes read so far and the total size of all files.
+/// Returns the number of lines written.
+pub(crate) fn export_search_results(
+    files: &[(String, Arc<CompressedSearchResults>)],
+    target: &str,
+    options: &ExportOptions,
+    mut progress: impl FnMut(u64, u64),
+) -> anyhow::Result<u64> {
+    let start = std::time::Instant::now();
+
+    let total_bytes = files
+        .iter()
+        .filter_map(|(path, _)| log_len(path).ok())
+        .sum();
+
+    let mut out = BufWriter::new(File::create(target)?);
+    let mut bytes_done = 0u64;
+    let mut written = 0u64;
+    let context = options.context_lines;
+
+    for (path, lines) in files {
+        if files.len() > 1 {
+            writeln!(out, "==> {path} <==")?;
+        }
+
+        let mut reader = BufReader::new(open_log(path)?);
+        let mut hits = lines.iter().peekable();
+        let mut buf = Vec::new();
+        let mut line_number = 0u64;
+        let mut last_written = 0u64;
+        let mut context_until = 0u64;
+
+        while hits.peek().is_some() || line_number < context_until {
+            buf.clear();
+            let bytes_read = reader.read_until(b'\n', &mut buf)?;
+            if bytes_read == 0 {
+                break;
+            }
+
+            // search results are 1-based line numbers
+            line_number += 1;
+            bytes_done += bytes_read as u64;
+
+            let is_hit = hits.peek() == Some(&line_number);
+            if is_hit {
+                hits.next();
+                context_until = line_number + context;
+            }
+            let before_next_hit = hits.peek().is_some_and(|hit| hit - line_number <= context);
+
+            if is_hit || line_number <= context_until || before_next_hit {
+                if context > 0 && last_written > 0 && last_written + 1 != line_number {
+                    out.write_all(b"--\n")?;
+                }
+                if options.line_numbers {
+                    write!(out, "{line_number}{}", if is_hit { ':' } else { '-' })?;
+                }
+                out.write_all(&buf)?;
+                if !buf.ends_with(b"\n") {
+                    out.write_all(b"\n")?;
+                }
+
+                last_written = line_number;
+                written += 1;
+            }
+
+            if line_number % 10_000 == 0 {
+                progress(bytes_done, total_bytes);
+            }
+        }
+    }
+
+    out.flush()?;
+    progress(total_bytes, total_bytes);
+
+    info!(
+        "export_search_results:: wrote {written} lines to {target} in {}ms",
+        start.elapsed().as_millis()
+    );
+    Ok(written)
+}
+
+/// Handles `gorl export <file> <from>-<to> <target> [--line-numbers]`, which writes the
+/// 1-based lines `from..=to` of `file` to `target` without opening a window.
+pub(crate) fn export_from_command_line(args: &[String]) -> anyhow::Result<()> {
+    let usage = "usage: gorl export <file> <from>-<to> <target> [--line-numbers]";
+
+    let (file, range, target) = match args {
+        [file, range, target, ..] => (file, range, target),
+        _ => anyhow::bail!(usage),
+    };
+    let line_numbers = args[3..].iter().any(|arg| arg == "--line-numbers");
+
+    let lines = parse_line_range(range).ok_or_else(|| anyhow::anyhow!(usage))?;
+
+    let mut view = LineBasedFileView::new(File::open(file)?)?;
+    let mut out = BufWriter::new(File::create(target)?);
+    let written = view.export_lines(lines, &mut out, line_numbers)?;
+    out.flush()?;
+
+    info!("EXPORT: exported {written} lines of {file} to {target}");
+    Ok(())
+}
+
+/// Parses a 1-based, inclusive "from-to" into 0-based lines.
+pub(crate) fn parse_line_range(text: &str) -> Option<Range<u64>> {
+    let (first, last) = text.split_once('-')?;
+    let first: u64 = first.trim().parse().ok()?;
+    let last: u64 = last.trim().parse().ok()?;
+    Some(first.saturating_sub(1)..last)
+}
+
+/// One line as shown in the main window, with its colours and search hits.
+pub(crate) struct StyledLine {
+    /// 1-based
+    pub number: u64,
+    pub text: String,
+    pub style: LineStyle,
+    /// Byte ranges of the search hits in `text`, ascending and not overlapping.
+    pub hits: Vec<Range<usize>>,
+}
+
+impl StyledLine {
+    /// Splits the text into pieces that have the same colours and are either entirely a
+    /// search hit or not at all.
+    fn segments(&self) -> Vec<(&str, Option<HighlightMatch>, bool)> {
+        let styled = self.style.segments(self.text.len());
+
+        let mut bounds: Vec<usize> = styled
+            .iter()
+            .map(|(range, _)| range.start)
+            .chain(self.hits.iter().flat_map(|hit| [hit.start, hit.end]))
+            .chain([self.text.len()])
+            .filter(|pos| *pos <= self.text.len() && self.text.is_char_boundary(*pos))
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut start = 0;
+        bounds
+            .into_iter()
+            .filter(|end| *end > 0)
+            .map(|end| {
+                let colors = styled
+                    .iter()
+                    .find(|(range, _)| range.start <= start && start < range.end)
+                    .and_then(|(_, colors)| *colors);
+                let is_hit = self.hits.iter().any(|hit| hit.start <= start && end <= hit.end);
+                let segment = (&self.text[start..end], colors, is_hit);
+                start = end;
+                segment
+            })
+            .collect()
+    }
+}
+
+const LINE_NUMBER_COLOR: (u8, u8, u8) = (128, 128, 128);
+const HIT_COLOR: (u8, u8, u8) = (255, 255, 0);
+
+fn html_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn html_text_style(style: TextStyle) -> String {
+    let mut css = String::new();
+    if style.bold {
+        css.push_str("font-weight:bold;");
+    }
+    if style.italic {
+        css.push_str("font-style:italic;");
+    }
+    if style.underline {
+        css.push_str("text-decoration:underline;");
+    }
+    css
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders the lines as a `<pre>` block keeping the highlight colours, with search hits marked.
+pub(crate) fn render_html(lines: &[StyledLine], line_numbers: bool) -> String {
+    let width = lines.last().map_or(1, |line| line.number.to_string().len());
+    let mut html = String::from("<pre style=\"font-family:Consolas,monospace;font-size:9pt\">");
+
+    for line in lines {
+        match line.style.line {
+            Some(highlight) => html.push_str(&format!(
+                "<span style=\"color:{};background-color:{};{}\">",
+                html_color(highlight.fg_color),
+                html_color(highlight.bg_color),
+                html_text_style(highlight.text_style)
+            )),
+            None => html.push_str("<span>"),
+        }
+
+        if line_numbers {
+            html.push_str(&format!(
+                "<span style=\"color:{}\">{:>width$} </span>",
+                html_color(LINE_NUMBER_COLOR),
+                line.number
+            ));
+        }
+
+        for (text, colors, is_hit) in line.segments() {
+            let fg = colors.map(|c| {
+                format!("color:{};{}", html_color(c.fg_color), html_text_style(c.text_style))
+            });
+            let bg = if is_hit {
+                Some(HIT_COLOR)
+            } else {
+                colors.map(|c| c.bg_color)
+            };
+            let bg = bg.map(|bg| format!("background-color:{}", html_color(bg)));
+
+            match (fg, bg) {
+                (None, None) => html.push_str(&escape_html(text)),
+                (fg, bg) => html.push_str(&format!(
+                    "<span style=\"{}{}\">{}</span>",
+                    fg.unwrap_or_default(),
+                    bg.unwrap_or_default(),
+                    escape_html(text)
+                )),
+            }
+        }
+
+        html.push_str("</span>\n");
+    }
+
+    html.push_str("</pre>");
+    html
+}
+
+fn rtf_text_style(style: TextStyle) -> String {
+    let mut control_words = String::new();
+    if style.bold {
+        control_words.push_str("\\b");
+    }
+    if style.italic {
+        control_words.push_str("\\i");
+    }
+    if style.underline {
+        control_words.push_str("\\ul");
+    }
+    control_words
+}
+
+fn escape_rtf(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\t' => escaped.push_str("\\tab "),
+            c if c.is_ascii() && !c.is_ascii_control() => escaped.push(c),
+            c if c.is_ascii_control() => {}
+            // RTF wants UTF-16 code units as signed 16 bit numbers, with '?' as fallback
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{}?", *unit as i16));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// Renders the lines as an RTF document, e.g. for pasting into Word or Outlook.
+pub(crate) fn render_rtf(lines: &[StyledLine], line_numbers: bool) -> String {
+    // 1 = line numbers, 2 = hits, followed by the colours of the highlights
+    let mut colors = vec![LINE_NUMBER_COLOR, HIT_COLOR];
+    let mut color_index = |color: (u8, u8, u8)| match colors.iter().position(|c| *c == color) {
+        Some(idx) => idx + 1,
+        None => {
+            colors.push(color);
+            colors.len()
+        }
+    };
+
+    let width = lines.last().map_or(1, |line| line.number.to_string().len());
+    let mut body = String::new();
+
+    for line in lines {
+        if line_numbers {
+            body.push_str(&format!("{{\\cf1 {:>width$} }}", line.number));
+        }
+
+        for (text, colors, is_hit) in line.segments() {
+            let (fg, bg, text_style) = match colors {
+                Some(colors) => (
+                    color_index(colors.fg_color),
+                    color_index(colors.bg_color),
+                    rtf_text_style(colors.text_style),
+                ),
+                None => (0, 0, String::new()),
+            };
+            let bg = if is_hit { 2 } else { bg };
+            body.push_str(&format!(
+                "{{\\cf{fg}\\highlight{bg}\\cb{bg}\\chcbpat{bg}{text_style} {}}}",
+                escape_rtf(text)
+            ));
+        }
+
+        body.push_str("\\line\n");
+    }
+
+    let color_table: String = colors
+        .iter()
+        .map(|(r, g, b)| format!("\\red{r}\\green{g}\\blue{b};"))
+        .collect();
+
+    format!(
+        "{{\\rtf1\\ansi\\deff0{{\\fonttbl{{\\f0\\fmodern Consolas;}}}}{{\\colortbl;{color_table}}}\\f0\\fs18\n{body}}}"
+    )
+}
+
+/// Modal dialog asking how search results should be exported.
+#[derive(Clone)]
+pub(crate) struct ExportDialog {
+    wnd: gui::WindowModal,
+    line_numbers_check_box: gui::CheckBox,
+    context_txt_box: gui::Edit,
+    ok_button: gui::Button,
+    cancel_button: gui::Button,
+    result: Rc<RwLock<Option<ExportOptions>>>,
+}
+
+impl ExportDialog {
+    /// Shows the dialog and blocks until it is closed. Returns `None` if the user cancelled.
+    pub fn show(parent: &impl GuiParent, options: &ExportOptions) -> Option<ExportOptions> {
+        let dialog = Self::new(parent, options);
+
+        if let Err(e) = dialog.wnd.show_modal() {
+            error!("ExportDialog: could not show dialog: {e}");
+            return None;
+        }
+
+        let result = dialog.result.read().unwrap().clone();
+        result
+    }
+
+    fn new(parent: &impl GuiParent, options: &ExportOptions) -> Self {
+        let wnd = gui::WindowModal::new(
+            parent,
+            gui::WindowModalOpts {
+                title: "GORL - Export search results".to_owned(),
+                size: (300, 110),
+                ..Default::default()
+            },
+        );
+
+        let line_numbers_check_box = gui::CheckBox::new(
+            &wnd,
+            gui::CheckBoxOpts {
+                text: "Line numbers".to_owned(),
+                position: (10, 12),
+                size: (280, 20),
+                check_state: if options.line_numbers {
+                    CheckState::Checked
+                } else {
+                    CheckState::Unchecked
+                },
+                ..Default::default()
+            },
+        );
+
+        let _context_label = gui::Label::new(
+            &wnd,
+            gui::LabelOpts {
+                text: "Context lines:".to_owned(),
+                position: (10, 46),
+                size: (100, 20),
+                ..Default::default()
+            },
+        );
+
+        let context_txt_box = gui::Edit::new(
+            &wnd,
+            gui::EditOpts {
+                text: options.context_lines.to_string(),
+                position: (120, 42),
+                width: 60,
+                height: 24,
+                edit_style: ES::LEFT | ES::NUMBER,
+                ..Default::default()
+            },
+        );
+
+        let ok_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "OK".to_owned(),
+                position: (100, 78),
+                button_style: BS::DEFPUSHBUTTON | BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::Repos),
+                ..Default::default()
+            },
+        );
+
+        let cancel_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "Cancel".to_owned(),
+                position: (200, 78),
+                button_style: BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::Repos),
+                ..Default::default()
+            },
+        );
+
+        let new_self = Self {
+            wnd,
+            line_numbers_check_box,
+            context_txt_box,
+            ok_button,
+            cancel_button,
+            result: Rc::new(RwLock::new(None)),
+        };
+
+        new_self.events();
+        new_self
+    }
+
+    fn events(&self) {
+        self.wnd.on().wm_create({
+            let myself = self.clone();
+            move |_msg| {
+                let _ = crate::utils::try_set_dark_mode(myself.wnd.hwnd());
+                Ok(0)
+            }
+        });
+
+        self.ok_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                let options = ExportOptions {
+                    line_numbers: myself.line_numbers_check_box.is_checked(),
+                    context_lines: myself.context_txt_box.text().trim().parse().unwrap_or(0),
+                };
+                info!("ExportDialog: OK => {options:?}");
+                *myself.result.write().unwrap() = Some(options);
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+
+        self.cancel_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::filtered_view::RowMapping;
+use crate::lineview::LineBasedFileView;
+use crate::search::search_in_file;
+use crate::timestamp::{TimeRange, TimestampIndex};
+
+/// One step of a [`FilterPipeline`]. Every stage narrows down the lines left by the previous one.
+///
+/// Internally tagged, so the stages survive the round trip through `config`, which only knows
+/// maps and strings.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "kind")]
+pub(crate) enum FilterStage {
+    Include { query: String },
+    Exclude { query: String },
+    /// Keeps the lines with timestamps between `from` and `to`, as entered by the user, so a
+    /// saved pipeline with times of day works for the logs of any day.
+    IncludeTime { from: String, to: String },
+}
+
+/// A pipeline stored under a name in the settings, so it can be reused for other files.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct NamedFilterPipeline {
+    pub name: String,
+    pub stages: Vec<FilterStage>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct FilterPipeline {
+    pub stages: Vec<FilterStage>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct PipelineResult {
+    /// Number of lines left after each stage, in stage order.
+    pub stage_counts: Vec<u64>,
+    pub rows: RowMapping,
+}
+
+impl PipelineResult {
+    /// Formats the line counts as `total → stage 1 → stage 2 → ...`.
+    pub fn describe(&self, line_count: u64) -> String {
+        std::iter::once(line_count)
+            .chain(self.stage_counts.iter().copied())
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(" → ")
+    }
+}
+
+impl FilterPipeline {
+    /// Parses a pipeline with one stage per line:
+    ///
+    /// ```text
+    /// include component=auth
+    /// exclude DEBUG
+    /// include time between 14:30 and 14:45
+    /// use my-saved-pipeline
+    /// ```
+    ///
+    /// Times are accepted as by `parse_user_time`. `use` inserts the stages of the saved
+    /// pipeline with that name. Empty lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str, saved: &[NamedFilterPipeline]) -> anyhow::Result<Self> {
+        let mut stages = Vec::new();
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (keyword, argument) = line.split_once(' ').unwrap_or((line, ""));
+            let argument = argument.trim();
+
+            match keyword {
+                "include" if argument.to_ascii_lowercase().starts_with("time between ") => {
+                    let times = &argument["time between ".len()..];
+                    match times.to_ascii_lowercase().find(" and ") {
+                        Some(separator) => stages.push(FilterStage::IncludeTime {
+                            from: times[..separator].trim().to_owned(),
+                            to: times[separator + 5..].trim().to_owned(),
+                        }),
+                        None => bail!("expected 'include time between <time> and <time>'"),
+                    }
+                }
+                "include" => stages.push(FilterStage::Include {
+                    query: argument.to_owned(),
+                }),
+                "exclude" => stages.push(FilterStage::Exclude {
+                    query: argument.to_owned(),
+                }),
+                "use" => match saved.iter().find(|p| p.name == argument) {
+                    Some(pipeline) => stages.extend(pipeline.stages.iter().cloned()),
+                    None => bail!("no saved filter named '{argument}'"),
+                },
+                _ => bail!("unknown filter stage '{line}'"),
+            }
+        }
+
+        Ok(Self { stages })
+    }
+
+    /// The 0-based lines of each `IncludeTime` stage, in stage order, as `run` expects them.
+    /// Looking them up only reads a few pages of `view`, so unlike `run` this is cheap enough
+    /// for the UI thread.
+    pub fn time_lines<R: Read + Seek>(
+        &self,
+        timestamps: Option<&TimestampIndex>,
+        view: &mut LineBasedFileView<R>,
+    ) -> anyhow::Result<Vec<Range<u64>>> {
+        self.stages
+            .iter()
+            .filter_map(|stage| match stage {
+                FilterStage::IncludeTime { from, to } => Some((from, to)),
+                _ => None,
+            })
+            .map(|(from, to)| {
+                let timestamps =
+                    timestamps.ok_or_else(|| anyhow!("the file has no timestamps to filter by"))?;
+                let range = timestamps
+                    .range()
+                    .ok_or_else(|| anyhow!("the file has no timestamps to filter by"))?;
+                let time_range =
+                    TimeRange::between(from, to, &timestamps.format, range, timestamps.offset)
+                        .ok_or_else(|| anyhow!("invalid time range '{from}' and '{to}'"))?;
+                Ok(timestamps.lines_in(&time_range, view))
+            })
+            .collect()
+    }
+
+    /// Runs all stages against the file at `path`, which has `line_count` lines. `time_lines`
+    /// are the lines of the `IncludeTime` stages, see `time_lines`.
+    pub fn run(
+        &self,
+        path: &str,
+        line_count: u64,
+        time_lines: &[Range<u64>],
+    ) -> anyhow::Result<PipelineResult> {
+        let mut rows = RowMapping::All;
+        let mut stage_counts = Vec::with_capacity(self.stages.len());
+        let mut time_lines = time_lines.iter().cloned();
+
+        for stage in &self.stages {
+            rows = stage.apply(rows, path, &mut time_lines)?;
+            stage_counts.push(rows.row_count(line_count));
+            info!("FilterPipeline: {stage} => {} lines", rows.row_count(line_count));
+        }
+
+        Ok(PipelineResult { stage_counts, rows })
+    }
+}
+
+impl FilterStage {
+    fn apply(
+        &self,
+        rows: RowMapping,
+        path: &str,
+        time_lines: &mut impl Iterator<Item = Range<u64>>,
+    ) -> anyhow::Result<RowMapping> {
+        Ok(match self {
+            FilterStage::Include { query } => rows.restricted_to(&Arc::new(search_in_file(query, path)?)),
+            FilterStage::Exclude { query } => rows.without(&Arc::new(search_in_file(query, path)?)),
+            FilterStage::IncludeTime { .. } => {
+                let lines = time_lines.next().ok_or_else(|| anyhow!("no lines for '{self}'"))?;
+                rows.restricted_to_range(lines)
+            }
+        })
+    }
+}
+
+impl Display for FilterStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterStage::Include { query } => write!(f, "include {query}"),
+            FilterStage::Exclude { query } => write!(f, "exclude {query}"),
+            FilterStage::IncludeTime { from, to } => write!(f, "include time between {from} and {to}"),
+        }
+    }
+}
+
+impl Display for FilterPipeline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for stage in &self.stages {
+            writeln!(f, "{stage}")?;
+        }
+        Ok(())
+    }
+}
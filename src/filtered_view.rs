@@ -0,0 +1,284 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::search::CompressedSearchResults;
+
+/// Maps the visual rows of a list view to 0-based lines of the underlying file.
+///
+/// `Only` shows just the lines contained in the search results (which are 1-based, as produced
+/// by `search_in_file`), while keeping their original line numbers. `AllExcept` shows every line
+/// that is *not* contained in the results. `Window` shows a contiguous range of 0-based lines,
+/// like the lines of a time range, except the `hidden` ones, without listing each line.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum RowMapping {
+    #[default]
+    All,
+    Only(Arc<CompressedSearchResults>),
+    AllExcept(Arc<CompressedSearchResults>),
+    Window {
+        lines: Range<u64>,
+        hidden: Option<Arc<CompressedSearchResults>>,
+    },
+}
+
+/// Number of results within the 0-based `lines`.
+fn count_within(results: &CompressedSearchResults, lines: &Range<u64>) -> u64 {
+    let index_of = |line: u64| results.lower_bound(line + 1).unwrap_or(results.get_count());
+    (index_of(lines.end) - index_of(lines.start)) as u64
+}
+
+impl RowMapping {
+    /// Returns the mapping that additionally hides all lines in `hidden`.
+    pub fn without(&self, hidden: &Arc<CompressedSearchResults>) -> RowMapping {
+        match self {
+            RowMapping::All => RowMapping::AllExcept(hidden.clone()),
+            RowMapping::Only(lines) => RowMapping::Only(Arc::new(lines.difference(hidden))),
+            RowMapping::AllExcept(other) => RowMapping::AllExcept(Arc::new(other.union(hidden))),
+            RowMapping::Window { lines, hidden: other } => RowMapping::Window {
+                lines: lines.clone(),
+                hidden: Some(match other {
+                    Some(other) => Arc::new(other.union(hidden)),
+                    None => hidden.clone(),
+                }),
+            },
+        }
+    }
+
+    /// Returns the mapping that only keeps the lines also contained in `lines`.
+    pub fn restricted_to(&self, lines: &Arc<CompressedSearchResults>) -> RowMapping {
+        match self {
+            RowMapping::All => RowMapping::Only(lines.clone()),
+            RowMapping::Only(shown) => RowMapping::Only(Arc::new(shown.intersection(lines))),
+            RowMapping::AllExcept(hidden) => RowMapping::Only(Arc::new(lines.difference(hidden))),
+            RowMapping::Window { lines: window, hidden } => {
+                let shown = lines.within(window);
+                RowMapping::Only(Arc::new(match hidden {
+                    Some(hidden) => shown.difference(hidden),
+                    None => shown,
+                }))
+            }
+        }
+    }
+
+    /// Returns the mapping that only keeps the 0-based `lines`, without listing each of them.
+    pub fn restricted_to_range(&self, lines: Range<u64>) -> RowMapping {
+        match self {
+            RowMapping::All => RowMapping::Window { lines, hidden: None },
+            RowMapping::Only(shown) => RowMapping::Only(Arc::new(shown.within(&lines))),
+            RowMapping::AllExcept(hidden) => RowMapping::Window {
+                lines,
+                hidden: Some(hidden.clone()),
+            },
+            RowMapping::Window { lines: window, hidden } => {
+                let start = window.start.max(lines.start);
+                RowMapping::Window {
+                    lines: start..window.end.min(lines.end).max(start),
+                    hidden: hidden.clone(),
+                }
+            }
+        }
+    }
+
+    pub fn row_count(&self, line_count: u64) -> u64 {
+        match self {
+            RowMapping::All => line_count,
+            RowMapping::Only(lines) => lines.get_count() as u64,
+            RowMapping::AllExcept(hidden) => line_count.saturating_sub(hidden.get_count() as u64),
+            RowMapping::Window { lines, hidden } => {
+                let lines = lines.start.min(line_count)..lines.end.min(line_count);
+                let hidden = hidden.as_ref().map_or(0, |hidden| count_within(hidden, &lines));
+                (lines.end - lines.start).saturating_sub(hidden)
+            }
+        }
+    }
+
+    pub fn line_of_row(&self, row: u64) -> Option<u64> {
+        match self {
+            RowMapping::All => Some(row),
+            RowMapping::Only(lines) => lines.get(row as usize).map(|l| l - 1),
+            RowMapping::AllExcept(hidden) => Some(hidden.nth_missing(row) - 1),
+            RowMapping::Window { lines, hidden } => {
+                let line = match hidden {
+                    // skips the lines shown before the window
+                    Some(hidden) => {
+                        let hidden_before = count_within(hidden, &(0..lines.start));
+                        hidden.nth_missing(row + lines.start - hidden_before) - 1
+                    }
+                    None => lines.start + row,
+                };
+                (line < lines.end).then_some(line)
+            }
+        }
+    }
+
+    /// Returns the row showing `line`, or the row of the next visible line if `line` itself is
+    /// hidden. Lines after the last visible one map to the last row, except for `All` and
+    /// `AllExcept`, which don't know the line count and map them past it.
+    pub fn row_of_line(&self, line: u64) -> u64 {
+        match self {
+            RowMapping::All => line,
+            RowMapping::Only(lines) => lines
+                .lower_bound(line + 1)
+                .unwrap_or(lines.get_count())
+                .min(lines.get_count().saturating_sub(1)) as u64,
+            RowMapping::AllExcept(hidden) => {
+                line - hidden.lower_bound(line + 1).unwrap_or(hidden.get_count()) as u64
+            }
+            RowMapping::Window { lines, hidden } => {
+                let hidden_in = |range: Range<u64>| {
+                    hidden.as_ref().map_or(0, |hidden| count_within(hidden, &range))
+                };
+                let line = line.clamp(lines.start, lines.end.max(lines.start + 1) - 1);
+                let rows = (lines.end - lines.start).saturating_sub(hidden_in(lines.clone()));
+                (line - lines.start - hidden_in(lines.start..line)).min(rows.saturating_sub(1))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE_COUNT: u64 = 600;
+
+    /// 1-based lines in `0..LINE_COUNT`, about one in `every`, from a fixed seed.
+    fn results(every: u64, seed: u64) -> Arc<CompressedSearchResults> {
+        let mut state = seed;
+        Arc::new(
+            (1..=LINE_COUNT)
+                .filter(|_| {
+                    state = state
+                        .wrapping_mul(6_364_136_223_846_793_005)
+                        .wrapping_add(1_442_695_040_888_963_407);
+                    (state >> 40) < (1 << 24) / every
+                })
+                .collect(),
+        )
+    }
+
+    fn contains(results: &CompressedSearchResults, line: u64) -> bool {
+        results.iter().any(|l| l == line + 1)
+    }
+
+    /// The 0-based lines `mapping` shows, one by one.
+    fn visible(mapping: &RowMapping) -> Vec<u64> {
+        (0..LINE_COUNT)
+            .filter(|line| match mapping {
+                RowMapping::All => true,
+                RowMapping::Only(shown) => contains(shown, *line),
+                RowMapping::AllExcept(hidden) => !contains(hidden, *line),
+                RowMapping::Window { lines, hidden } => {
+                    lines.contains(line) && !hidden.as_ref().is_some_and(|h| contains(h, *line))
+                }
+            })
+            .collect()
+    }
+
+    fn assert_matches_brute_force(mapping: &RowMapping) {
+        let visible = visible(mapping);
+        let rows = visible.len() as u64;
+        assert_eq!(mapping.row_count(LINE_COUNT), rows, "{mapping:?}");
+
+        for (row, line) in visible.iter().enumerate() {
+            assert_eq!(mapping.line_of_row(row as u64), Some(*line), "{mapping:?} row {row}");
+        }
+        if matches!(mapping, RowMapping::Only(_) | RowMapping::Window { .. }) {
+            assert_eq!(mapping.line_of_row(rows), None, "{mapping:?} after the last row");
+        }
+
+        for line in 0..LINE_COUNT {
+            let row = mapping.row_of_line(line);
+            match visible.iter().position(|visible| *visible >= line) {
+                Some(expected) => assert_eq!(row, expected as u64, "{mapping:?} line {line}"),
+                // `All` and `AllExcept` don't know the line count to stop at the last row
+                None if matches!(mapping, RowMapping::All | RowMapping::AllExcept(_)) => {
+                    assert!(row >= rows.saturating_sub(1), "{mapping:?} line {line}")
+                }
+                None => assert_eq!(row, rows.saturating_sub(1), "{mapping:?} line {line}"),
+            }
+        }
+    }
+
+    fn sets() -> Vec<Arc<CompressedSearchResults>> {
+        vec![
+            Arc::new(CompressedSearchResults::new()),
+            results(2, 1),
+            results(7, 2),
+            results(200, 3),
+            Arc::new((1..=LINE_COUNT).collect()),
+            Arc::new([1, LINE_COUNT].into_iter().collect()),
+        ]
+    }
+
+    fn windows() -> Vec<Range<u64>> {
+        vec![
+            0..LINE_COUNT,
+            0..1,
+            1..LINE_COUNT - 1,
+            100..101,
+            250..250,
+            LINE_COUNT - 1..LINE_COUNT,
+        ]
+    }
+
+    #[test]
+    fn plain_mappings() {
+        assert_matches_brute_force(&RowMapping::All);
+        for set in sets() {
+            assert_matches_brute_force(&RowMapping::Only(set.clone()));
+            assert_matches_brute_force(&RowMapping::AllExcept(set));
+        }
+    }
+
+    #[test]
+    fn windows_with_hidden_lines() {
+        for lines in windows() {
+            assert_matches_brute_force(&RowMapping::Window {
+                lines: lines.clone(),
+                hidden: None,
+            });
+            for hidden in sets() {
+                assert_matches_brute_force(&RowMapping::Window {
+                    lines: lines.clone(),
+                    hidden: Some(hidden),
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn combined_mappings() {
+        let mut mappings = vec![RowMapping::All];
+        for set in sets() {
+            mappings.push(RowMapping::Only(set.clone()));
+            mappings.push(RowMapping::AllExcept(set));
+        }
+        for lines in windows() {
+            mappings.push(RowMapping::All.restricted_to_range(lines));
+        }
+
+        for mapping in &mappings {
+            let shown = visible(mapping);
+            for set in sets() {
+                let in_set = |line: &u64| contains(&set, *line);
+
+                let without = mapping.without(&set);
+                assert_matches_brute_force(&without);
+                assert_eq!(visible(&without), shown.iter().copied().filter(|l| !in_set(l)).collect::<Vec<_>>());
+
+                let restricted = mapping.restricted_to(&set);
+                assert_matches_brute_force(&restricted);
+                assert_eq!(visible(&restricted), shown.iter().copied().filter(in_set).collect::<Vec<_>>());
+            }
+            for lines in windows() {
+                let restricted = mapping.restricted_to_range(lines.clone());
+                assert_matches_brute_force(&restricted);
+                assert_eq!(
+                    visible(&restricted),
+                    shown.iter().copied().filter(|l| lines.contains(l)).collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use grep::matcher::{LineTerminator, Match, Matcher, NoCaptures, NoError};
+
+/// Longest pattern the bit-parallel algorithm can handle, one bit per pattern character.
+pub(crate) const MAX_PATTERN_LEN: usize = 64;
+
+/// Matches lines containing `pattern` with at most `max_distance` edits (insertions,
+/// deletions or substitutions of single characters), e.g. `conection refused` finds
+/// `connection refused` with a distance of 1.
+///
+/// Uses Myers' bit-parallel approximate string matching, so each line is scanned once.
+#[derive(Debug, Clone)]
+pub(crate) struct FuzzyMatcher {
+    /// For every character of the pattern, the bit mask of its positions.
+    peq: HashMap<char, u64>,
+    len: usize,
+    max_distance: u32,
+    case_insensitive: bool,
+}
+
+impl FuzzyMatcher {
+    pub fn new(pattern: &str, max_distance: u32, case_insensitive: bool) -> anyhow::Result<Self> {
+        let len = pattern.chars().count();
+        if len == 0 || len > MAX_PATTERN_LEN {
+            anyhow::bail!("fuzzy search needs 1 to {MAX_PATTERN_LEN} characters, got {len}");
+        }
+
+        let mut peq = HashMap::new();
+        for (i, c) in pattern.chars().enumerate() {
+            *peq.entry(fold(c, case_insensitive)).or_insert(0) |= 1 << i;
+        }
+
+        Ok(Self {
+            peq,
+            len,
+            max_distance,
+            case_insensitive,
+        })
+    }
+
+    /// Smallest edit distance between the pattern and any substring of `text`.
+    pub fn distance(&self, text: &str) -> u32 {
+        let last_bit = 1u64 << (self.len - 1);
+        let mut pv = u64::MAX >> (MAX_PATTERN_LEN - self.len);
+        let mut mv = 0u64;
+        let mut score = self.len as u32;
+        let mut best = score;
+
+        for c in text.chars() {
+            let eq = self.peq.get(&fold(c, self.case_insensitive)).copied().unwrap_or(0);
+            let xv = eq | mv;
+            let xh = ((eq & pv).wrapping_add(pv) ^ pv) | eq;
+            let mut ph = mv | !(xh | pv);
+            let mut mh = pv & xh;
+
+            if ph & last_bit != 0 {
+                score += 1;
+            } else if mh & last_bit != 0 {
+                score -= 1;
+            }
+
+            // no carry into the first row: a match may start anywhere in the text
+            ph <<= 1;
+            mh <<= 1;
+            pv = mh | !(xv | ph);
+            mv = ph & xv;
+
+            best = best.min(score);
+            if best == 0 {
+                break;
+            }
+        }
+
+        best
+    }
+}
+
+fn fold(c: char, case_insensitive: bool) -> char {
+    if case_insensitive {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c
+    }
+}
+
+impl Matcher for FuzzyMatcher {
+    type Captures = NoCaptures;
+    type Error = NoError;
+
+    /// Reports the first line at or after `at` within `max_distance`, as a whole.
+    fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, NoError> {
+        let mut start = at;
+
+        for line in haystack[at..].split(|b| *b == b'\n') {
+            let end = start + line.len();
+            if self.distance(&String::from_utf8_lossy(line)) <= self.max_distance {
+                return Ok(Some(Match::new(start, end)));
+            }
+            start = end + 1;
+        }
+
+        Ok(None)
+    }
+
+    fn new_captures(&self) -> Result<NoCaptures, NoError> {
+        Ok(NoCaptures::new())
+    }
+
+    fn line_terminator(&self) -> Option<LineTerminator> {
+        Some(LineTerminator::byte(b'\n'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smallest edit distance between `pattern` and any substring of `text`, by dynamic
+    /// programming over all prefixes.
+    fn brute_force(pattern: &str, text: &str) -> u32 {
+        let pattern: Vec<char> = pattern.chars().collect();
+        // a match may start anywhere, so the first row stays 0
+        let mut column: Vec<u32> = (0..=pattern.len() as u32).collect();
+        let mut best = column[pattern.len()];
+
+        for c in text.chars() {
+            let mut diagonal = column[0];
+            for (i, p) in pattern.iter().enumerate() {
+                let substituted = diagonal + u32::from(*p != c);
+                diagonal = column[i + 1];
+                column[i + 1] = substituted.min(column[i] + 1).min(column[i + 1] + 1);
+            }
+            best = best.min(column[pattern.len()]);
+        }
+
+        best
+    }
+
+    fn distance(pattern: &str, text: &str) -> u32 {
+        FuzzyMatcher::new(pattern, 0, false).unwrap().distance(text)
+    }
+
+    #[test]
+    fn exact_matches() {
+        assert_eq!(distance("connection refused", "connection refused"), 0);
+        assert_eq!(
+            distance("refused", "12:00:00 connection refused by host"),
+            0
+        );
+        assert_eq!(distance("x", "x"), 0);
+    }
+
+    #[test]
+    fn single_edits() {
+        // substitution, insertion and deletion
+        assert_eq!(distance("connection refused", "connectiun refused"), 1);
+        assert_eq!(distance("connection refused", "connecction refused"), 1);
+        assert_eq!(distance("connection refused", "conection refused"), 1);
+        assert_eq!(distance("conection", "host: connection refused"), 1);
+        assert_eq!(
+            distance("timeout", "no match here"),
+            brute_force("timeout", "no match here")
+        );
+    }
+
+    #[test]
+    fn longest_pattern() {
+        let pattern: String = (0..MAX_PATTERN_LEN)
+            .map(|i| (b'a' + (i % 26) as u8) as char)
+            .collect();
+        assert_eq!(distance(&pattern, &format!("<{pattern}>")), 0);
+
+        let mut typo = pattern.clone();
+        typo.replace_range(63..64, "?");
+        assert_eq!(distance(&pattern, &typo), 1);
+        typo.replace_range(0..1, "");
+        assert_eq!(distance(&pattern, &typo), 2);
+
+        let too_long = format!("{pattern}x");
+        assert!(FuzzyMatcher::new(&too_long, 1, false).is_err());
+    }
+
+    #[test]
+    fn empty_pattern() {
+        assert!(FuzzyMatcher::new("", 1, false).is_err());
+    }
+
+    #[test]
+    fn case_folding() {
+        let matcher = FuzzyMatcher::new("Connection ÄRGER", 0, true).unwrap();
+        assert_eq!(matcher.distance("CONNECTION ärger"), 0);
+        assert_eq!(matcher.distance("connection örger"), 1);
+        assert_eq!(distance("Connection", "connection"), 1);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let texts = [
+            "",
+            "a",
+            "abcabc",
+            "the quick brown fox",
+            "aaaaab",
+            "xyzzy",
+            "mississippi",
+        ];
+        let patterns = [
+            "a", "ab", "abc", "fox", "quack", "issip", "brwn fx", "zzzzzz",
+        ];
+        for pattern in patterns {
+            for text in texts {
+                assert_eq!(
+                    distance(pattern, text),
+                    brute_force(pattern, text),
+                    "{pattern} in {text}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finds_lines() {
+        let matcher = FuzzyMatcher::new("conection", 1, false).unwrap();
+        let haystack = b"first line\nconnection refused\nlast line";
+        assert_eq!(
+            matcher.find_at(haystack, 0).unwrap(),
+            Some(Match::new(11, 29))
+        );
+        assert_eq!(matcher.find_at(haystack, 12).unwrap(), None);
+    }
+}
@@ -1,60 +1,729 @@
-use grep::matcher::Matcher;
+use std::ops::Range;
+use std::path::Path;
+
+use aho_corasick::AhoCorasick;
+use globset::GlobBuilder;
+use grep::matcher::{Captures, Matcher};
 use grep::regex::{RegexMatcher, RegexMatcherBuilder};
-use serde_derive::Deserialize;
+use log::{error, warn};
+use regex::RegexSetBuilder;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::log_format::LogFormat;
 
 type HighlightColor = (u8, u8, u8);
 
-#[derive(Clone, Deserialize, Debug)]
+/// What part of a line a highlight rule colours.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "kind")]
+pub(crate) enum HighlightTarget {
+    /// The whole row, like a single rule did before rules could colour spans.
+    #[default]
+    Line,
+    /// Only the text matched by `expr`.
+    Match,
+    /// Only the text of the named capture groups of `expr`.
+    Groups { names: Vec<String> },
+}
+
+/// Font variations a rule can apply on top of its colours.
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub(crate) struct TextStyle {
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub italic: bool,
+    #[serde(default)]
+    pub underline: bool,
+}
+
+impl TextStyle {
+    pub fn is_plain(&self) -> bool {
+        *self == TextStyle::default()
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A highlight rule. All fields after `case_insensitive` are optional in `settings.ron`, so
+/// rules written before they existed keep working unchanged.
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
 pub(crate) struct HighlightSetting {
     pub fg_color: HighlightColor,
     pub bg_color: HighlightColor,
     pub expr: String,
     pub case_insensitive: bool,
+    #[serde(default)]
+    pub target: HighlightTarget,
+    /// Rules with a higher priority are evaluated first; equal priorities keep their order.
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    pub text_style: TextStyle,
+    /// If `false`, no further rules are evaluated on a line once this one matched.
+    #[serde(default = "default_true")]
+    pub continue_evaluating: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+/// When a profile is picked automatically for a file.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "kind")]
+pub(crate) enum ProfileCondition {
+    /// Glob on the file name, e.g. `nginx-*.log`, or on the whole path if it contains a
+    /// separator. Case-insensitive.
+    FileName { glob: String },
+    /// Regex matching the first line of the file.
+    FirstLine { expr: String },
+    /// The format detected from the first lines, see `LogFormat::detect`.
+    Format { format: LogFormat },
+}
+
+impl ProfileCondition {
+    fn matches(&self, path: &str, first_line: &str, format: Option<LogFormat>) -> bool {
+        match self {
+            ProfileCondition::FileName { glob } => {
+                let matcher = match GlobBuilder::new(glob).case_insensitive(true).build() {
+                    Ok(glob) => glob.compile_matcher(),
+                    Err(e) => {
+                        error!("HIGHLIGHTER: invalid file name glob {glob}: {e}");
+                        return false;
+                    }
+                };
+
+                if glob.contains(['/', '\\']) {
+                    matcher.is_match(path)
+                } else {
+                    Path::new(path)
+                        .file_name()
+                        .is_some_and(|name| matcher.is_match(name))
+                }
+            }
+            ProfileCondition::FirstLine { expr } => match RegexMatcher::new(expr) {
+                Ok(matcher) => matcher.is_match(first_line.as_bytes()).unwrap_or(false),
+                Err(e) => {
+                    error!("HIGHLIGHTER: invalid first line expression {expr}: {e}");
+                    false
+                }
+            },
+            ProfileCondition::Format { format: wanted } => format == Some(*wanted),
+        }
+    }
+}
+
+/// A named set of highlight rules, e.g. for the vocabulary of one service.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub(crate) struct HighlightProfile {
+    pub name: String,
+    pub rules: Vec<HighlightSetting>,
+    /// The profile is picked for a file if any of these match; without conditions it is only
+    /// ever chosen by hand.
+    #[serde(default)]
+    pub conditions: Vec<ProfileCondition>,
+}
+
+impl HighlightProfile {
+    /// The first of `profiles` with a condition matching the file at `path`, which starts with
+    /// `first_lines`.
+    pub fn select<'a, S: AsRef<str>>(
+        profiles: &'a [HighlightProfile],
+        path: &str,
+        first_lines: &[S],
+    ) -> Option<&'a HighlightProfile> {
+        let first_line = first_lines.first().map_or("", |line| line.as_ref());
+        let format = LogFormat::detect(first_lines);
+
+        profiles.iter().find(|profile| {
+            profile
+                .conditions
+                .iter()
+                .any(|condition| condition.matches(path, first_line, format))
+        })
+    }
+}
+
+/// Tells in one pass over a line which rules match it, so the individual matchers only have
+/// to run for the few rules that do.
+///
+/// Plain literals go into Aho-Corasick automatons, everything else into one `RegexSet`.
+#[derive(Clone)]
+struct RuleSet {
+    /// The automatons with the rule index of each of their patterns.
+    literals: Vec<(AhoCorasick, Vec<usize>)>,
+    regexes: Option<(regex::RegexSet, Vec<usize>)>,
+    /// Rules that could not be combined and have to be run on every line.
+    unfiltered: Vec<usize>,
+    rule_count: usize,
+}
+
+impl RuleSet {
+    fn new(settings: &[(RegexMatcher, HighlightSetting)]) -> Self {
+        let mut exact = vec![];
+        let mut ignoring_case = vec![];
+        let mut others = vec![];
+
+        for (idx, (_, setting)) in settings.iter().enumerate() {
+            let is_literal = !setting.expr.is_empty() && regex::escape(&setting.expr) == setting.expr;
+            // the automaton only folds ASCII, while the matchers also fold `k` and `s` into the
+            // Kelvin sign and the long s
+            let folds_as_ascii =
+                setting.expr.is_ascii() && !setting.expr.contains(['k', 'K', 's', 'S']);
+            match (is_literal, setting.case_insensitive) {
+                (true, false) => exact.push(idx),
+                (true, true) if folds_as_ascii => ignoring_case.push(idx),
+                _ => others.push(idx),
+            }
+        }
+
+        let mut unfiltered = vec![];
+
+        let mut literals = vec![];
+        for (rules, ignore_case) in [(exact, false), (ignoring_case, true)] {
+            if rules.is_empty() {
+                continue;
+            }
+
+            let built = AhoCorasick::builder()
+                .ascii_case_insensitive(ignore_case)
+                .build(rules.iter().map(|idx| &settings[*idx].1.expr));
+            match built {
+                Ok(automaton) => literals.push((automaton, rules)),
+                Err(e) => {
+                    warn!("HIGHLIGHTER: could not combine literal rules: {e}");
+                    unfiltered.extend(rules);
+                }
+            }
+        }
+
+        let regexes = if others.is_empty() {
+            None
+        } else {
+            let patterns = others.iter().map(|idx| {
+                let setting = &settings[*idx].1;
+                if setting.case_insensitive {
+                    format!("(?i:{})", setting.expr)
+                } else {
+                    format!("(?:{})", setting.expr)
+                }
+            });
+
+            match RegexSetBuilder::new(patterns).build() {
+                Ok(set) => Some((set, others)),
+                Err(e) => {
+                    warn!("HIGHLIGHTER: could not combine rules: {e}");
+                    unfiltered.extend(others);
+                    None
+                }
+            }
+        };
+
+        Self {
+            literals,
+            regexes,
+            unfiltered,
+            rule_count: settings.len(),
+        }
+    }
+
+    /// For every rule, whether it may match `text`.
+    fn candidates(&self, text: &str) -> Vec<bool> {
+        let mut candidates = vec![false; self.rule_count];
+
+        for (automaton, rules) in &self.literals {
+            for m in automaton.find_overlapping_iter(text) {
+                candidates[rules[m.pattern().as_usize()]] = true;
+            }
+        }
+
+        if let Some((set, rules)) = &self.regexes {
+            for pattern in set.matches(text).iter() {
+                candidates[rules[pattern]] = true;
+            }
+        }
+
+        for idx in &self.unfiltered {
+            candidates[*idx] = true;
+        }
+        candidates
+    }
 }
 
 #[derive(Clone)]
 pub(crate) struct Highlighter {
+    /// All rules as given, including disabled ones and in their original order.
+    rules: Vec<HighlightSetting>,
     settings: Vec<(RegexMatcher, HighlightSetting)>,
+    rule_set: RuleSet,
 }
 
-#[derive(Debug,Copy,Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub(crate) struct HighlightMatch {
     pub fg_color: HighlightColor,
     pub bg_color: HighlightColor,
+    pub text_style: TextStyle,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HighlightSpan {
+    pub range: Range<usize>,
+    pub colors: HighlightMatch,
+}
+
+/// How a line is coloured: the row colours of the first matching whole-line rule, plus the
+/// spans coloured by rules that only target parts of the line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LineStyle {
+    pub line: Option<HighlightMatch>,
+    /// Byte ranges, ascending and not overlapping.
+    pub spans: Vec<HighlightSpan>,
+}
+
+impl LineStyle {
+    /// Splits `0..text_len` into consecutive pieces, each with the colours to draw it in.
+    /// Pieces not covered by any span get the row colours.
+    pub fn segments(&self, text_len: usize) -> Vec<(Range<usize>, Option<HighlightMatch>)> {
+        let mut segments = vec![];
+        let mut pos = 0;
+
+        for span in &self.spans {
+            if pos < span.range.start {
+                segments.push((pos..span.range.start, self.line));
+            }
+            segments.push((span.range.clone(), Some(span.colors)));
+            pos = span.range.end;
+        }
+
+        if pos < text_len {
+            segments.push((pos..text_len, self.line));
+        }
+        segments
+    }
+}
+
+/// Styles of the lines held in the line cache of a `LineBasedFileView`, so a row is only
+/// evaluated again once the view has moved on to another part of the file.
+#[derive(Debug, Default)]
+pub(crate) struct StyleCache {
+    lines: Range<u64>,
+    styles: Vec<Option<LineStyle>>,
+}
+
+impl StyleCache {
+    /// The style of `line`, computed by `style` unless cached. `cached_lines` are the lines
+    /// currently in the line cache; the styles of all others are dropped.
+    pub fn get_or_insert_with(
+        &mut self,
+        cached_lines: Range<u64>,
+        line: u64,
+        style: impl FnOnce() -> LineStyle,
+    ) -> LineStyle {
+        if self.lines != cached_lines {
+            self.styles = vec![None; (cached_lines.end - cached_lines.start) as usize];
+            self.lines = cached_lines;
+        }
+
+        if !self.lines.contains(&line) {
+            return style();
+        }
+
+        self.styles[(line - self.lines.start) as usize]
+            .get_or_insert_with(style)
+            .clone()
+    }
+
+    /// Drops all styles, e.g. after the rules or the file changed.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
 }
 
 impl Highlighter {
     pub fn new(highlight_settings: Vec<HighlightSetting>) -> Self {
-        let settings = highlight_settings
+        let mut settings: Vec<_> = highlight_settings
             .iter()
+            .filter(|setting| setting.enabled)
             .filter_map(Self::create_matcher_from)
             .collect();
+        // stable, so rules of equal priority keep their order
+        settings.sort_by_key(|(_, setting)| std::cmp::Reverse(setting.priority));
+
+        Self {
+            rules: highlight_settings,
+            rule_set: RuleSet::new(&settings),
+            settings,
+        }
+    }
+
+    pub fn rules(&self) -> &[HighlightSetting] {
+        &self.rules
+    }
+
+    /// The enabled, valid rules in the order they are evaluated.
+    pub fn active_rules(&self) -> impl Iterator<Item = &HighlightSetting> {
+        self.settings.iter().map(|(_, setting)| setting)
+    }
 
-        Self { settings }
+    /// For every rule of `active_rules`, whether its expression matches `text`.
+    pub fn matching_rules(&self, text: &str) -> Vec<bool> {
+        let mut matching = self.rule_set.candidates(text);
+        for idx in &self.rule_set.unfiltered {
+            matching[*idx] = self.settings[*idx].0.is_match(text.as_bytes()).unwrap_or(false);
+        }
+        matching
     }
 
-    pub fn matches(&self, text: &str) -> Option<HighlightMatch> {
+    /// Evaluates the rules on `text` by priority, until one that doesn't continue evaluating
+    /// matched. Where the spans of several rules overlap, the rule evaluated first wins.
+    ///
+    /// Rules colouring spans only count as matched if they colour some text: the empty matches
+    /// of expressions like `a*` or `\b` neither colour anything nor stop the evaluation.
+    pub fn style(&self, text: &str) -> LineStyle {
+        let mut style = LineStyle::default();
         if self.settings.is_empty() {
-            return None;
+            return style;
         }
 
         let haystack = text.as_bytes();
+        let candidates = self.rule_set.candidates(text);
+        let mut spans = vec![];
+
+        for ((matcher, setting), _) in self.settings.iter().zip(candidates).filter(|(_, c)| *c) {
+            let colors = HighlightMatch {
+                fg_color: setting.fg_color,
+                bg_color: setting.bg_color,
+                text_style: setting.text_style,
+            };
+            let spans_before = spans.len();
+
+            let matched = match &setting.target {
+                HighlightTarget::Line => {
+                    let is_match = matcher.is_match(haystack).unwrap_or(false);
+                    if is_match && style.line.is_none() {
+                        style.line = Some(colors);
+                    }
+                    is_match
+                }
+                HighlightTarget::Match => {
+                    let _ = matcher.find_iter(haystack, |m| {
+                        if !m.is_empty() {
+                            spans.push(HighlightSpan {
+                                range: m.start()..m.end(),
+                                colors,
+                            });
+                        }
+                        true
+                    });
+                    spans.len() > spans_before
+                }
+                HighlightTarget::Groups { names } => {
+                    let indices: Vec<usize> =
+                        names.iter().filter_map(|name| matcher.capture_index(name)).collect();
+                    let mut caps = match matcher.new_captures() {
+                        Ok(caps) => caps,
+                        Err(_) => continue,
+                    };
 
-        self.settings
+                    let _ = matcher.captures_iter(haystack, &mut caps, |caps| {
+                        for m in indices.iter().filter_map(|idx| caps.get(*idx)) {
+                            if m.is_empty() {
+                                continue;
+                            }
+                            spans.push(HighlightSpan {
+                                range: m.start()..m.end(),
+                                colors,
+                            });
+                        }
+                        true
+                    });
+                    spans.len() > spans_before
+                }
+            };
+
+            if matched && !setting.continue_evaluating {
+                break;
+            }
+        }
+
+        style.spans = Self::flatten(spans);
+        style
+    }
+
+    /// Resolves overlapping spans (given in rule order) into non-overlapping, ascending ones.
+    fn flatten(spans: Vec<HighlightSpan>) -> Vec<HighlightSpan> {
+        let spans: Vec<HighlightSpan> = spans.into_iter().filter(|s| !s.range.is_empty()).collect();
+        if spans.len() <= 1 {
+            return spans;
+        }
+
+        let mut bounds: Vec<usize> = spans
             .iter()
-            .find(|(matcher, _)| matcher.is_match(haystack).unwrap_or(false))
-            .map(|(_, s)| HighlightMatch {
-                fg_color: s.fg_color,
-                bg_color: s.bg_color,
-            })
+            .flat_map(|s| [s.range.start, s.range.end])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut flat: Vec<HighlightSpan> = vec![];
+        for piece in bounds.windows(2) {
+            let (start, end) = (piece[0], piece[1]);
+            let winner = spans
+                .iter()
+                .find(|s| s.range.start <= start && end <= s.range.end);
+
+            if let Some(winner) = winner {
+                match flat.last_mut() {
+                    Some(last) if last.range.end == start && last.colors == winner.colors => {
+                        last.range.end = end;
+                    }
+                    _ => flat.push(HighlightSpan {
+                        range: start..end,
+                        colors: winner.colors,
+                    }),
+                }
+            }
+        }
+
+        flat
     }
 
-    fn create_matcher_from(setting: &HighlightSetting) -> Option<(RegexMatcher, HighlightSetting)> {
+    /// Compiles the expression of `setting`, e.g. to check a rule before using it.
+    pub fn build_matcher(setting: &HighlightSetting) -> anyhow::Result<RegexMatcher> {
         let matcher = RegexMatcherBuilder::new()
             .case_insensitive(setting.case_insensitive)
-            .build(setting.expr.as_str())
-            .ok()?;
+            .build(setting.expr.as_str())?;
+        Ok(matcher)
+    }
 
+    fn create_matcher_from(setting: &HighlightSetting) -> Option<(RegexMatcher, HighlightSetting)> {
+        let matcher = Self::build_matcher(setting).ok()?;
         Some((matcher, setting.clone()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors(shade: u8) -> HighlightMatch {
+        HighlightMatch {
+            fg_color: (shade, 0, 0),
+            bg_color: (255, 255, 255),
+            text_style: TextStyle::default(),
+        }
+    }
+
+    fn span(range: Range<usize>, shade: u8) -> HighlightSpan {
+        HighlightSpan {
+            range,
+            colors: colors(shade),
+        }
+    }
+
+    fn rule(expr: &str, target: HighlightTarget, shade: u8) -> HighlightSetting {
+        HighlightSetting {
+            fg_color: (shade, 0, 0),
+            bg_color: (255, 255, 255),
+            expr: expr.to_owned(),
+            case_insensitive: false,
+            target,
+            priority: 0,
+            text_style: TextStyle::default(),
+            continue_evaluating: true,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn flatten_overlapping_spans() {
+        // the span given first wins where they overlap
+        assert_eq!(
+            Highlighter::flatten(vec![span(0..10, 1), span(5..15, 2)]),
+            vec![span(0..10, 1), span(10..15, 2)]
+        );
+        assert_eq!(
+            Highlighter::flatten(vec![span(5..15, 2), span(0..10, 1)]),
+            vec![span(0..5, 1), span(5..15, 2)]
+        );
+        assert_eq!(
+            Highlighter::flatten(vec![span(5..8, 1), span(0..20, 2)]),
+            vec![span(0..5, 2), span(5..8, 1), span(8..20, 2)]
+        );
+        assert_eq!(
+            Highlighter::flatten(vec![span(0..20, 2), span(5..8, 1)]),
+            vec![span(0..20, 2)]
+        );
+    }
+
+    #[test]
+    fn flatten_sorts_and_merges() {
+        assert_eq!(
+            Highlighter::flatten(vec![span(10..12, 1), span(0..2, 2), span(4..4, 3)]),
+            vec![span(0..2, 2), span(10..12, 1)]
+        );
+        assert_eq!(
+            Highlighter::flatten(vec![span(0..3, 1), span(3..6, 1), span(2..4, 1)]),
+            vec![span(0..6, 1)]
+        );
+        assert_eq!(
+            Highlighter::flatten(vec![span(0..3, 1), span(3..6, 2)]),
+            vec![span(0..3, 1), span(3..6, 2)]
+        );
+        assert_eq!(Highlighter::flatten(vec![]), vec![]);
+    }
+
+    #[test]
+    fn segments() {
+        let style = LineStyle {
+            line: None,
+            spans: vec![span(2..4, 1), span(4..6, 2), span(8..10, 3)],
+        };
+        assert_eq!(
+            style.segments(12),
+            vec![
+                (0..2, None),
+                (2..4, Some(colors(1))),
+                (4..6, Some(colors(2))),
+                (6..8, None),
+                (8..10, Some(colors(3))),
+                (10..12, None),
+            ]
+        );
+        assert_eq!(style.segments(10).last(), Some(&(8..10, Some(colors(3)))));
+
+        let line = Some(colors(9));
+        let style = LineStyle {
+            line,
+            spans: vec![span(0..3, 1)],
+        };
+        assert_eq!(style.segments(5), vec![(0..3, Some(colors(1))), (3..5, line)]);
+
+        let plain = LineStyle { line, spans: vec![] };
+        assert_eq!(plain.segments(5), vec![(0..5, line)]);
+        assert_eq!(plain.segments(0), vec![]);
+    }
+
+    #[test]
+    fn spans_of_matches_and_groups() {
+        let highlighter = Highlighter::new(vec![
+            rule(r"\d+", HighlightTarget::Match, 1),
+            rule(
+                r"(?P<key>[a-z]+)=(?P<value>\w+)",
+                HighlightTarget::Groups {
+                    names: vec!["key".to_owned(), "missing".to_owned()],
+                },
+                2,
+            ),
+            rule("b=", HighlightTarget::Line, 3),
+        ]);
+
+        let style = highlighter.style("a=1 b=22 c=x");
+        assert_eq!(style.line, Some(colors(3)));
+        assert_eq!(
+            style.spans,
+            vec![
+                span(0..1, 2),
+                span(2..3, 1),
+                span(4..5, 2),
+                span(6..8, 1),
+                span(9..10, 2),
+            ]
+        );
+        assert_eq!(highlighter.style("nothing here"), LineStyle::default());
+    }
+
+    #[test]
+    fn empty_matches_do_not_stop_evaluating() {
+        let stopping = |expr: &str, target: HighlightTarget| HighlightSetting {
+            priority: 1,
+            continue_evaluating: false,
+            ..rule(expr, target, 1)
+        };
+        let groups = || HighlightTarget::Groups {
+            names: vec!["x".to_owned()],
+        };
+
+        for first in [
+            stopping("x*", HighlightTarget::Match),
+            stopping(r"\b", HighlightTarget::Match),
+            stopping("(?P<x>x*)", groups()),
+            stopping(r"(?P<x>x?)\b", groups()),
+        ] {
+            let highlighter = Highlighter::new(vec![first.clone(), rule("b", HighlightTarget::Line, 2)]);
+            let style = highlighter.style("abc");
+            assert_eq!(style.line, Some(colors(2)), "{}", first.expr);
+            assert_eq!(style.spans, vec![], "{}", first.expr);
+        }
+
+        // a non-empty match still stops the evaluation
+        for first in [stopping("b*", HighlightTarget::Match), stopping("(?P<x>b*)", groups())] {
+            let highlighter = Highlighter::new(vec![first.clone(), rule("b", HighlightTarget::Line, 2)]);
+            let style = highlighter.style("abc");
+            assert_eq!(style.line, None, "{}", first.expr);
+            assert_eq!(style.spans, vec![span(1..2, 1)], "{}", first.expr);
+        }
+    }
+
+    #[test]
+    fn prefilter_matches_matchers() {
+        let case_insensitive = |expr: &str| HighlightSetting {
+            case_insensitive: true,
+            ..rule(expr, HighlightTarget::Line, 0)
+        };
+        let highlighter = Highlighter::new(vec![
+            rule("error", HighlightTarget::Line, 0),
+            case_insensitive("Error"),
+            case_insensitive("ÄRGER"),
+            case_insensitive("Kelvin"),
+            case_insensitive("strasse"),
+            case_insensitive("fail"),
+            rule(r"\d{3}", HighlightTarget::Match, 0),
+            case_insensitive("warn|timeout"),
+            rule("a.b", HighlightTarget::Line, 0),
+            HighlightSetting {
+                enabled: false,
+                ..rule("disabled", HighlightTarget::Line, 0)
+            },
+            rule("(", HighlightTarget::Line, 0),
+            HighlightSetting {
+                priority: 5,
+                ..rule("[x]", HighlightTarget::Line, 0)
+            },
+            rule("", HighlightTarget::Line, 0),
+        ]);
+
+        let active: Vec<&str> = highlighter.active_rules().map(|r| r.expr.as_str()).collect();
+        assert_eq!(active.len(), 11);
+        assert_eq!(active[0], "[x]");
+        assert!(!active.contains(&"disabled") && !active.contains(&"("));
+
+        let lines = [
+            "",
+            "an error occurred",
+            "an ERROR occurred",
+            "an Error occurred",
+            "ärger im büro",
+            "Ärger im Büro",
+            "\u{212A}ELVIN and \u{212A}elvin",
+            "kelvin",
+            "stra\u{17F}\u{17F}e",
+            "STRASSE",
+            "FaIl after 100 ms",
+            "12 34 56",
+            "WARN: TimeOut",
+            "a-b axb ab",
+            "disabled (",
+            "[x] x",
+        ];
+        for line in lines {
+            let expected: Vec<bool> = highlighter
+                .settings
+                .iter()
+                .map(|(matcher, _)| matcher.is_match(line.as_bytes()).unwrap())
+                .collect();
+            assert_eq!(highlighter.rule_set.candidates(line), expected, "{line}");
+            assert_eq!(highlighter.matching_rules(line), expected, "{line}");
+        }
+    }
+}
@@ -1,8 +1,8 @@
 use crate::{SETTINGS, settings};
 use log::debug;
 
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
-use std::ops::{Bound, RangeBounds};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::{Bound, Range, RangeBounds};
 
 #[derive(Debug, Copy, Clone)]
 struct LastBound {
@@ -18,6 +18,51 @@ pub struct LineChunk {
     right_offset: u64,
 }
 
+/// The part of a file holding a range of lines, as found through the `LineChunk` index.
+///
+/// The byte range is aligned to whole pages, so it may start before and end after the
+/// requested lines; `first_line` is the 0-based line starting at `offsets.start`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LineSlice {
+    pub lines: Range<u64>,
+    pub offsets: Range<u64>,
+    pub first_line: u64,
+}
+
+/// Copies the lines of `slice` from `reader` to `out` byte-for-byte, optionally prefixed with
+/// their 1-based line number. Returns the number of lines written.
+pub fn copy_lines<R: Read + Seek>(
+    reader: &mut R,
+    slice: &LineSlice,
+    out: &mut impl Write,
+    line_numbers: bool,
+) -> anyhow::Result<u64> {
+    reader.seek(SeekFrom::Start(slice.offsets.start))?;
+    let mut lines = BufReader::new(reader.take(slice.offsets.end - slice.offsets.start));
+
+    let mut buf = Vec::new();
+    let mut line = slice.first_line;
+    let mut written = 0;
+
+    while line < slice.lines.end {
+        buf.clear();
+        if lines.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+
+        if line >= slice.lines.start {
+            if line_numbers {
+                write!(out, "{}:", line + 1)?;
+            }
+            out.write_all(&buf)?;
+            written += 1;
+        }
+        line += 1;
+    }
+
+    Ok(written)
+}
+
 #[derive(Debug)]
 pub struct LineBasedFileView<R: std::io::Read + std::io::Seek> {
     reader: BufReader<R>,
@@ -87,6 +132,11 @@ impl<R: Seek + Read> LineBasedFileView<R> {
         })
     }
 
+    /// Number of lines of each `LineChunk` but the last one.
+    pub fn lines_per_page(&self) -> u64 {
+        self.def_cache_size
+    }
+
     pub fn page_count(&self) -> usize {
         self.lines.len()
     }
@@ -99,6 +149,44 @@ impl<R: Seek + Read> LineBasedFileView<R> {
         }
     }
 
+    /// Translates the 0-based `lines` into the byte offsets of the pages containing them.
+    /// Returns `None` if none of the lines exist.
+    pub fn slice(&self, lines: Range<u64>) -> Option<LineSlice> {
+        let lines = lines.start..lines.end.min(self.line_count());
+        if lines.is_empty() {
+            return None;
+        }
+
+        let first = self.lines.partition_point(|c| c.lst_line <= lines.start);
+        let last = self.lines.partition_point(|c| c.lst_line < lines.end);
+        let (first, last) = (self.lines.get(first)?, self.lines.get(last)?);
+
+        Some(LineSlice {
+            offsets: first.left_offset..last.right_offset,
+            first_line: first.fst_line,
+            lines,
+        })
+    }
+
+    /// Writes the 0-based `lines` to `out` exactly as they are in the file, see `copy_lines`.
+    pub fn export_lines(
+        &mut self,
+        lines: Range<u64>,
+        out: &mut impl Write,
+        line_numbers: bool,
+    ) -> anyhow::Result<u64> {
+        match self.slice(lines) {
+            Some(slice) => copy_lines(&mut self.reader, &slice, out, line_numbers),
+            None => Ok(0),
+        }
+    }
+
+    /// The 0-based lines currently held in the line cache.
+    pub fn cached_lines(&self) -> Range<u64> {
+        self.last_bounds
+            .map_or(0..0, |bounds| bounds.left..bounds.right)
+    }
+
     pub fn get_line(&mut self, index: u64) -> Result<String, String> {
         if let Some(last_bounds) = &self.last_bounds {
             if last_bounds.left <= index && index < last_bounds.right {
@@ -0,0 +1,69 @@
+use grep::matcher::Matcher;
+use grep::regex::RegexMatcher;
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+
+/// Well-known log line layouts, recognised from the first lines of a file.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    /// One JSON object per line.
+    Json,
+    /// Apache/nginx common or combined access log.
+    Apache,
+    /// BSD (RFC 3164) or IETF (RFC 5424) syslog.
+    Syslog,
+    /// `key=value` pairs, e.g. `level=info msg="started"`.
+    Logfmt,
+}
+
+/// Number of non-empty lines looked at by `detect`.
+pub(crate) const DETECT_LINES: usize = 10;
+
+lazy_static! {
+    static ref FORMAT_MATCHERS: Vec<(LogFormat, RegexMatcher)> = [
+        (LogFormat::Json, r#"^\s*\{.*\}\s*$"#),
+        (
+            LogFormat::Apache,
+            r#"^\S+ \S+ \S+ \[[^\]]+\] "[A-Z]+ [^"]*" \d{3} "#,
+        ),
+        (
+            LogFormat::Syslog,
+            r#"^(<\d{1,3}>)?([A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2} |\d \d{4}-\d{2}-\d{2}T)"#,
+        ),
+        (
+            LogFormat::Logfmt,
+            r#"^\s*[\w.-]+=("[^"]*"|\S+)(\s+[\w.-]+=("[^"]*"|\S+))+\s*$"#,
+        ),
+    ]
+    .into_iter()
+    .map(|(format, expr)| (format, RegexMatcher::new(expr).unwrap()))
+    .collect();
+}
+
+impl LogFormat {
+    fn of_line(line: &str) -> Option<LogFormat> {
+        FORMAT_MATCHERS
+            .iter()
+            .find(|(_, matcher)| matcher.is_match(line.as_bytes()).unwrap_or(false))
+            .map(|(format, _)| *format)
+    }
+
+    /// The format of most of the first `DETECT_LINES` non-empty `lines`, if there is one.
+    pub fn detect<S: AsRef<str>>(lines: &[S]) -> Option<LogFormat> {
+        let formats: Vec<Option<LogFormat>> = lines
+            .iter()
+            .map(|line| line.as_ref())
+            .filter(|line| !line.trim().is_empty())
+            .take(DETECT_LINES)
+            .map(Self::of_line)
+            .collect();
+
+        formats
+            .iter()
+            .flatten()
+            .copied()
+            .find(|candidate| {
+                formats.iter().filter(|f| **f == Some(*candidate)).count() * 2 > formats.len()
+            })
+    }
+}
@@ -0,0 +1,319 @@
+use std::io::{BufRead, BufReader};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::highlighter::{HighlightMatch, TextStyle};
+use crate::log_reader::open_log;
+use crate::search::CompressedSearchResults;
+
+/// Severity of a log line, ordered from least to most severe.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub(crate) enum LogLevel {
+    Trace = 1,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+/// Only the start of a line is searched for a level, so messages mentioning e.g. "error"
+/// later on don't count.
+const SCAN_PREFIX: usize = 160;
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 6] = [
+        LogLevel::Trace,
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Warn,
+        LogLevel::Error,
+        LogLevel::Fatal,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogLevel::Trace => "TRACE",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+            LogLevel::Fatal => "FATAL",
+        }
+    }
+
+    fn from_raw(raw: u8) -> Option<LogLevel> {
+        Self::ALL.get((raw as usize).checked_sub(1)?).copied()
+    }
+
+    /// The level spelled by `token`, ignoring case.
+    fn from_token(token: &str) -> Option<LogLevel> {
+        const SPELLINGS: [(LogLevel, &[&str]); 6] = [
+            (LogLevel::Trace, &["TRACE", "TRC", "VERBOSE", "VRB", "FINEST", "FINER", "T", "V"]),
+            (LogLevel::Debug, &["DEBUG", "DBG", "DEBG", "FINE", "D"]),
+            (LogLevel::Info, &["INFO", "INF", "INFORMATION", "NOTICE", "I"]),
+            (LogLevel::Warn, &["WARN", "WARNING", "WRN", "W"]),
+            (LogLevel::Error, &["ERROR", "ERR", "ERRO", "EROR", "E"]),
+            (
+                LogLevel::Fatal,
+                &[
+                    "FATAL", "FTL", "CRITICAL", "CRIT", "PANIC", "EMERG", "ALERT", "SEVERE", "F",
+                ],
+            ),
+        ];
+
+        SPELLINGS
+            .iter()
+            .find(|(_, spellings)| spellings.iter().any(|s| s.eq_ignore_ascii_case(token)))
+            .map(|(level, _)| *level)
+    }
+
+    /// Finds the level in the first word at the start of `line` that spells one. Upper case
+    /// words count anywhere there, others only at the very start or after one of `[="<(|`,
+    /// as in `level=info` or `[warn]`. Single letters are too common for that and only count
+    /// when enclosed, as in `<E>`.
+    pub fn detect(line: &str) -> Option<LogLevel> {
+        let mut end = line.len().min(SCAN_PREFIX);
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        let prefix = &line[..end];
+
+        let mut start = None;
+        for (i, c) in prefix.char_indices().chain([(prefix.len(), ' ')]) {
+            if c.is_ascii_alphabetic() {
+                start.get_or_insert(i);
+                continue;
+            }
+
+            let token_start = match start.take() {
+                Some(token_start) => token_start,
+                None => continue,
+            };
+            let token = &prefix[token_start..i];
+            let level = match Self::from_token(token) {
+                Some(level) => level,
+                None => continue,
+            };
+
+            let upper_case = token.bytes().all(|b| b.is_ascii_uppercase());
+            let delimited = matches!(
+                prefix[..token_start].chars().next_back(),
+                None | Some('[' | '=' | '"' | '<' | '(' | '|')
+            );
+            if token.len() == 1 {
+                let enclosed = delimited
+                    && matches!(
+                        prefix[i..].chars().next(),
+                        Some(']' | '>' | ')' | '|' | '"')
+                    );
+                if enclosed {
+                    return Some(level);
+                }
+            } else if upper_case || delimited {
+                return Some(level);
+            }
+        }
+
+        None
+    }
+
+    /// Colours for lines of this level that no highlight rule colours.
+    pub fn colors(&self) -> Option<HighlightMatch> {
+        let (fg_color, bg_color, bold) = match self {
+            LogLevel::Trace => ((160, 160, 160), (255, 255, 255), false),
+            LogLevel::Debug => ((110, 110, 110), (255, 255, 255), false),
+            LogLevel::Info => return None,
+            LogLevel::Warn => ((0, 0, 0), (255, 230, 140), false),
+            LogLevel::Error => ((255, 255, 255), (200, 40, 40), false),
+            LogLevel::Fatal => ((255, 255, 255), (120, 0, 60), true),
+        };
+
+        Some(HighlightMatch {
+            fg_color,
+            bg_color,
+            text_style: TextStyle {
+                bold,
+                ..Default::default()
+            },
+        })
+    }
+}
+
+/// The level of every line of a file, packed into 4 bits per line.
+///
+/// Lines without a level of their own, like the lines of a stack trace, get the level of the
+/// line before them, so they are shown and hidden together with it.
+#[derive(Default)]
+pub(crate) struct LevelIndex {
+    nibbles: Vec<u8>,
+    len: u64,
+}
+
+impl LevelIndex {
+    /// Detects the levels of all lines of the file at `path`.
+    pub fn build(path: &str) -> anyhow::Result<Self> {
+        let mut reader = BufReader::new(open_log(path)?);
+        let mut index = Self::default();
+        let mut buf = Vec::new();
+        let mut previous = None;
+
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+
+            let level = LogLevel::detect(&String::from_utf8_lossy(&buf)).or(previous);
+            index.push(level);
+            previous = level;
+        }
+
+        Ok(index)
+    }
+
+    fn push(&mut self, level: Option<LogLevel>) {
+        let raw = level.map_or(0, |level| level as u8);
+        if self.len % 2 == 0 {
+            self.nibbles.push(raw);
+        } else if let Some(last) = self.nibbles.last_mut() {
+            *last |= raw << 4;
+        }
+        self.len += 1;
+    }
+
+    /// The level of the 0-based `line`.
+    pub fn get(&self, line: u64) -> Option<LogLevel> {
+        let byte = self.nibbles.get((line / 2) as usize)?;
+        let raw = if line % 2 == 0 { byte & 0x0f } else { byte >> 4 };
+        LogLevel::from_raw(raw)
+    }
+
+    /// The 1-based numbers of all lines of level `min` or above, like search results.
+    pub fn lines_at_least(&self, min: LogLevel) -> CompressedSearchResults {
+        (0..self.len)
+            .filter(|line| self.get(*line).is_some_and(|level| level >= min))
+            .map(|line| line + 1)
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for LevelIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LevelIndex")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spellings() {
+        let spellings = [
+            ("TRACE", LogLevel::Trace),
+            ("VERBOSE", LogLevel::Trace),
+            ("FINER", LogLevel::Trace),
+            ("DEBUG", LogLevel::Debug),
+            ("DBG", LogLevel::Debug),
+            ("FINE", LogLevel::Debug),
+            ("INFO", LogLevel::Info),
+            ("INFORMATION", LogLevel::Info),
+            ("NOTICE", LogLevel::Info),
+            ("WARN", LogLevel::Warn),
+            ("WARNING", LogLevel::Warn),
+            ("ERROR", LogLevel::Error),
+            ("ERR", LogLevel::Error),
+            ("EROR", LogLevel::Error),
+            ("FATAL", LogLevel::Fatal),
+            ("CRITICAL", LogLevel::Fatal),
+            ("PANIC", LogLevel::Fatal),
+            ("SEVERE", LogLevel::Fatal),
+        ];
+
+        for (spelling, level) in spellings {
+            assert_eq!(LogLevel::from_token(spelling), Some(level), "{spelling}");
+            assert_eq!(
+                LogLevel::from_token(&spelling.to_lowercase()),
+                Some(level),
+                "{spelling}"
+            );
+            let line = format!("2024-03-01 12:00:00.123 {spelling} [main] started");
+            assert_eq!(LogLevel::detect(&line), Some(level), "{line}");
+        }
+        assert_eq!(LogLevel::from_token("ERRORS"), None);
+        assert_eq!(LogLevel::from_token(""), None);
+    }
+
+    #[test]
+    fn delimiters() {
+        assert_eq!(
+            LogLevel::detect("ts=2024-03-01T12:00:00Z level=info msg=started"),
+            Some(LogLevel::Info)
+        );
+        assert_eq!(
+            LogLevel::detect("2024-03-01 12:00:00 [warn] disk almost full"),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(
+            LogLevel::detect("12:00:00 <E> connection refused"),
+            Some(LogLevel::Error)
+        );
+        assert_eq!(
+            LogLevel::detect("{\"level\":\"debug\",\"msg\":\"x\"}"),
+            Some(LogLevel::Debug)
+        );
+        assert_eq!(
+            LogLevel::detect("12:00:00 (trace) entering"),
+            Some(LogLevel::Trace)
+        );
+        assert_eq!(
+            LogLevel::detect("12:00:00 |fatal| out of memory"),
+            Some(LogLevel::Fatal)
+        );
+        assert_eq!(
+            LogLevel::detect("warning: unused variable"),
+            Some(LogLevel::Warn)
+        );
+        assert_eq!(
+            LogLevel::detect("12:00:00 [E] connection refused"),
+            Some(LogLevel::Error)
+        );
+    }
+
+    #[test]
+    fn words_within_the_message() {
+        assert_eq!(
+            LogLevel::detect("12:00:00 connection error while reading"),
+            None
+        );
+        assert_eq!(LogLevel::detect("12:00:00 everything is fine"), None);
+        assert_eq!(
+            LogLevel::detect("12:00:00 raising an alert for disk usage"),
+            None
+        );
+        assert_eq!(LogLevel::detect("12:00:00 E connection refused"), None);
+        assert_eq!(LogLevel::detect("12:00:00 Info about the session"), None);
+        assert_eq!(LogLevel::detect("12:00:00 <I think> so"), None);
+        // the first word spelling a level wins
+        assert_eq!(
+            LogLevel::detect("12:00:00 INFO retrying after error"),
+            Some(LogLevel::Info)
+        );
+        assert_eq!(
+            LogLevel::detect("12:00:00 [info] ERROR count reset"),
+            Some(LogLevel::Info)
+        );
+    }
+
+    #[test]
+    fn only_the_start_of_a_line() {
+        let line = format!("{} ERROR", "x".repeat(SCAN_PREFIX));
+        assert_eq!(LogLevel::detect(&line), None);
+        let line = format!("{} ERROR", "ä".repeat(SCAN_PREFIX / 2 - 3));
+        assert_eq!(LogLevel::detect(&line), Some(LogLevel::Error));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+use log::debug;
+
+lazy_static! {
+    /// Logs made of parts of other files, by the name they are opened with, see `open_log`.
+    static ref CHAINED_LOGS: RwLock<HashMap<String, Arc<ChainedLog>>> =
+        RwLock::new(HashMap::new());
+}
+
+static NEXT_CHAINED_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A piece of a `ChainedLog`.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Bytes of the source with that index.
+    Bytes { source: usize, range: Range<u64> },
+    /// A line break the sources don't have, e.g. after a last line without one.
+    Newline,
+}
+
+/// A log made of byte ranges of other files one after the other, like a rotation set or
+/// several files merged by time. It is read through a `ChainedReader` without copying the
+/// files anywhere.
+#[derive(Debug, Default)]
+pub(crate) struct ChainedLog {
+    pub sources: Vec<PathBuf>,
+    segments: Vec<Segment>,
+    /// Where each segment starts in the chained content.
+    starts: Vec<u64>,
+    len: u64,
+}
+
+impl ChainedLog {
+    pub fn new(sources: Vec<PathBuf>) -> Self {
+        Self {
+            sources,
+            ..Default::default()
+        }
+    }
+
+    /// Appends `range` of the source with index `source`, extending the last segment if it
+    /// ends where `range` starts.
+    pub fn push(&mut self, source: usize, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+        let len = range.end - range.start;
+
+        match self.segments.last_mut() {
+            Some(Segment::Bytes {
+                source: last_source,
+                range: last_range,
+            }) if *last_source == source && last_range.end == range.start => {
+                last_range.end = range.end;
+            }
+            _ => {
+                self.starts.push(self.len);
+                self.segments.push(Segment::Bytes { source, range });
+            }
+        }
+        self.len += len;
+    }
+
+    /// Appends a line break.
+    pub fn push_newline(&mut self) {
+        self.starts.push(self.len);
+        self.segments.push(Segment::Newline);
+        self.len += 1;
+    }
+
+    /// Number of bytes of the chained content.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn reader(self: &Arc<Self>) -> ChainedReader {
+        ChainedReader {
+            log: self.clone(),
+            files: self.sources.iter().map(|_| None).collect(),
+            pos: 0,
+        }
+    }
+
+    /// Makes the log readable through `open_log` under a new name like `<rotated 3>`, until
+    /// it is `unregister`ed.
+    pub fn register(self: &Arc<Self>, kind: &str) -> String {
+        let name = format!("<{kind} {}>", NEXT_CHAINED_ID.fetch_add(1, Ordering::Relaxed));
+        CHAINED_LOGS
+            .write()
+            .unwrap()
+            .insert(name.clone(), self.clone());
+        name
+    }
+
+    pub fn unregister(name: &str) {
+        if CHAINED_LOGS.write().unwrap().remove(name).is_none() {
+            debug!("LOG READER: {name} was not registered");
+        }
+    }
+}
+
+/// Reads a `ChainedLog`. The sources are opened on first use, each reader has its own handles,
+/// so several threads can read the same log at once.
+pub(crate) struct ChainedReader {
+    log: Arc<ChainedLog>,
+    files: Vec<Option<File>>,
+    pos: u64,
+}
+
+impl Read for ChainedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.log.len {
+            return Ok(0);
+        }
+
+        let segment = self.log.starts.partition_point(|start| *start <= self.pos) - 1;
+        let in_segment = self.pos - self.log.starts[segment];
+
+        let read = match &self.log.segments[segment] {
+            Segment::Newline => {
+                buf[0] = b'\n';
+                1
+            }
+            Segment::Bytes { source, range } => {
+                let file = match &mut self.files[*source] {
+                    Some(file) => file,
+                    file => file.insert(File::open(&self.log.sources[*source])?),
+                };
+                let left = (range.end - range.start - in_segment).min(buf.len() as u64) as usize;
+                file.seek(SeekFrom::Start(range.start + in_segment))?;
+                let read = file.read(&mut buf[..left])?;
+                if read == 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        format!("{} got shorter", self.log.sources[*source].display()),
+                    ));
+                }
+                read
+            }
+        };
+
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for ChainedReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.log.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+
+        self.pos = pos.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "seek before the start of the log",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// A log file as read by the views and searches: a file, or a `ChainedLog`.
+pub(crate) enum LogReader {
+    File(File),
+    Chained(ChainedReader),
+}
+
+impl Read for LogReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            LogReader::File(file) => file.read(buf),
+            LogReader::Chained(chained) => chained.read(buf),
+        }
+    }
+}
+
+impl Seek for LogReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            LogReader::File(file) => file.seek(pos),
+            LogReader::Chained(chained) => chained.seek(pos),
+        }
+    }
+}
+
+fn chained_log(path: &str) -> Option<Arc<ChainedLog>> {
+    CHAINED_LOGS.read().unwrap().get(path).cloned()
+}
+
+/// Opens the log at `path`, which is either a file or the name of a registered `ChainedLog`.
+pub(crate) fn open_log(path: &str) -> std::io::Result<LogReader> {
+    match chained_log(path) {
+        Some(log) => Ok(LogReader::Chained(log.reader())),
+        None => Ok(LogReader::File(File::open(path)?)),
+    }
+}
+
+/// `true` if `path` names a registered `ChainedLog` rather than a file.
+pub(crate) fn is_chained(path: &str) -> bool {
+    chained_log(path).is_some()
+}
+
+/// The size in bytes of the log at `path`, see `open_log`.
+pub(crate) fn log_len(path: &str) -> std::io::Result<u64> {
+    match chained_log(path) {
+        Some(log) => Ok(log.len()),
+        None => Ok(std::fs::metadata(path)?.len()),
+    }
+}
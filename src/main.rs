@@ -1,9 +1,23 @@
 mod control_window;
+mod export;
+mod filter;
+mod filtered_view;
+mod fuzzy;
 mod highlighter;
 mod lineview;
+mod log_format;
+mod log_level;
+mod log_reader;
 mod main_window;
+mod merge;
+mod minimap;
+mod prompt;
+mod rotation;
+mod rule_editor;
+mod rule_stats;
 mod search;
 mod settings;
+mod timestamp;
 mod utils;
 
 use crate::control_window::ControlPanel;
@@ -18,6 +32,11 @@ lazy_static! {
 fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export") {
+        return export::export_from_command_line(&args[1..]);
+    }
+
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .max_blocking_threads(SETTINGS.read().unwrap().max_nb_of_ui_threads) // basically the limit of log file one can open
@@ -1,34 +1,149 @@
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
 use std::rc::Rc;
-use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
 
-use crate::highlighter::Highlighter;
-use crate::lineview::LineBasedFileView;
+use crate::export::{render_html, render_rtf, StyledLine};
+use crate::filter::{FilterPipeline, NamedFilterPipeline, PipelineResult};
+use crate::filtered_view::RowMapping;
+use crate::highlighter::{
+    HighlightProfile, HighlightSetting, Highlighter, LineStyle, StyleCache, TextStyle,
+};
+use crate::log_format::DETECT_LINES;
+use crate::log_level::{LevelIndex, LogLevel};
+use crate::log_reader::{open_log, LogReader};
+use crate::lineview::{copy_lines, LineBasedFileView};
+use crate::merge::{merge_files, MergedLog};
+use crate::minimap::Minimap;
 use winsafe::msg::WndMsg;
 use winsafe::msg::wm::SetFont;
 
-use crate::search::SearchWindow;
-use flume::Receiver;
+use crate::prompt::{ChoiceDialog, PromptDialog};
+use crate::rotation::{concat_rotation, rotation_set, rotation_set_matching, RotatedLog};
+use crate::rule_editor::RuleEditorDialog;
+use crate::rule_stats::{count_rule_hits, RuleStats, RuleStatsPanel};
+use crate::search::{search_in_file, CompressedSearchResults, FinishedSearch, SearchWindow};
+use crate::settings::FileExcludePatterns;
+use crate::timestamp::{format_millis, parse_user_time, TimeRange, TimestampIndex};
+use flume::{Receiver, Sender};
+use lazy_static::lazy_static;
 use log::{debug, error, info};
-use winsafe::co::{CDDS, CHARSET, CLIP, FW, LVS, LVS_EX, OUT_PRECIS, PITCH, QUALITY, VK};
+use winsafe::co::{CDDS, CHARSET, CLIP, DLGID, FW, LVS, LVS_EX, MB, OUT_PRECIS, PITCH, QUALITY, VK};
 use winsafe::gui::{Horz, ListViewOpts, Vert};
-use winsafe::{COLORREF, HFONT, HWND, SIZE, WString, co, gui, prelude::*};
+use winsafe::msg::lvm;
+use winsafe::{COLORREF, HBRUSH, HDC, HFONT, HWND, RECT, SIZE, WString, co, gui, prelude::*};
 
 use crate::SETTINGS;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub(crate) enum MwMessage {
     JumpTo(u64),
+    ShowFiltered(Arc<CompressedSearchResults>),
+    ExcludedLines {
+        file: String,
+        patterns: Vec<String>,
+        lines: Arc<CompressedSearchResults>,
+    },
+    PipelineApplied {
+        file: String,
+        result: PipelineResult,
+    },
+    /// A search of the search window finished on its thread, see `SearchWindow::run_search`.
+    SearchDone(Arc<FinishedSearch>),
+    SearchFinished,
+    /// Jump to the first of `lines`, or to the one after the focused line.
+    JumpToNextHit {
+        lines: Arc<CompressedSearchResults>,
+        first: bool,
+    },
+    RuleStats {
+        file: String,
+        generation: u64,
+        stats: Arc<RuleStats>,
+    },
+    LevelsDetected {
+        file: String,
+        levels: Arc<LevelIndex>,
+    },
+    /// The lines of `levels` of level `min_level` or above, see `update_level_lines`.
+    LevelLines {
+        levels: Arc<LevelIndex>,
+        min_level: LogLevel,
+        lines: Arc<CompressedSearchResults>,
+    },
+    TimestampsIndexed {
+        file: String,
+        timestamps: Arc<TimestampIndex>,
+    },
+    FilesMerged(Arc<MergedLog>),
+    RotationConcatenated(Arc<RotatedLog>),
 }
 
+/// A file shown in one of the main windows, so other windows can search it and route jumps to it.
+#[derive(Clone)]
+pub(crate) struct OpenFile {
+    pub window_id: usize,
+    pub path: String,
+    pub transmitter: Sender<MwMessage>,
+}
+
+lazy_static! {
+    pub(crate) static ref OPEN_FILES: RwLock<Vec<OpenFile>> = RwLock::new(Vec::new());
+}
+
+static NEXT_WINDOW_ID: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Clone)]
 pub(crate) struct GorlMainWindow {
     pub(crate) wnd: gui::WindowMain,
+    window_id: usize,
     list_view: gui::ListView,
-    view: Rc<RwLock<Option<LineBasedFileView<File>>>>,
+    level_combo: gui::ComboBox,
+    view: Rc<RwLock<Option<LineBasedFileView<LogReader>>>>,
+    current_file: Rc<RwLock<Option<String>>>,
+    row_mapping: Rc<RwLock<RowMapping>>,
+    last_filter: Rc<RwLock<Option<RowMapping>>>,
+    filter_description: Rc<RwLock<Option<String>>>,
+    filter_active: Rc<RwLock<bool>>,
+    last_pipeline: Rc<RwLock<FilterPipeline>>,
+    exclude_patterns: Rc<RwLock<Vec<String>>>,
+    excluded_lines: Rc<RwLock<Option<Arc<CompressedSearchResults>>>>,
     search_window: SearchWindow,
+    minimap: Minimap,
     inbox: Receiver<MwMessage>,
-    highlighter: Highlighter, //transmitter: Sender<MwMessage>,
+    transmitter: Sender<MwMessage>,
+    /// The highlight rules of this window, see `edit_highlight_rules`.
+    highlighter: Rc<RwLock<Highlighter>>,
+    style_cache: Rc<RwLock<StyleCache>>,
+    rule_stats_panel: RuleStatsPanel,
+    /// Bumped whenever the rules or the file change, so outdated hit counts are dropped.
+    rule_stats_generation: Arc<AtomicU64>,
+    levels: Rc<RwLock<Option<Arc<LevelIndex>>>>,
+    /// Only lines of this level or above are shown, once the levels are known.
+    min_level: Rc<RwLock<Option<LogLevel>>>,
+    level_lines: Rc<RwLock<Option<Arc<CompressedSearchResults>>>>,
+    timestamps: Rc<RwLock<Option<Arc<TimestampIndex>>>>,
+    /// Only lines within this time range are shown, see `filter_time_range`.
+    time_range: Rc<RwLock<Option<TimeRange>>>,
+    /// The 0-based lines of `time_range`.
+    time_lines: Rc<RwLock<Option<Range<u64>>>>,
+    /// Set while showing several files merged by time, see `merge`.
+    merged: Rc<RwLock<Option<Arc<MergedLog>>>>,
+    /// Set while showing a rotation set as one file, see `open_rotation`.
+    rotated: Rc<RwLock<Option<Arc<RotatedLog>>>>,
+    /// Name of the highlight profile in use, if any.
+    profile: Rc<RwLock<Option<String>>>,
+    /// Profile chosen by hand, which then stays in use for all files dropped into this window.
+    manual_profile: Rc<RwLock<Option<String>>>,
+    /// Set once the rules were changed in the rule editor. Like a manual profile, they then
+    /// stay in use for all files dropped into this window, until a profile is chosen.
+    rules_edited: Rc<RwLock<bool>>,
+    /// Variations of the list font for highlight rules that are bold, italic or underlined.
+    styled_fonts: Rc<RwLock<HashMap<TextStyle, HFONT>>>,
 }
 
 static CHECK_INBOX: co::WM = unsafe { co::WM::from_raw(0x1234) };
@@ -53,11 +168,24 @@ impl GorlMainWindow {
             },
         );
 
+        let level_combo = gui::ComboBox::new(
+            &wnd,
+            gui::ComboBoxOpts {
+                position: (10, 8),
+                width: 160,
+                items: std::iter::once("All levels".to_owned())
+                    .chain(LogLevel::ALL.iter().map(|l| format!("{} and above", l.label())))
+                    .collect(),
+                selected_item: Some(0),
+                ..Default::default()
+            },
+        );
+
         let list_view = gui::ListView::new(
             &wnd,
             ListViewOpts {
-                position: (10, 10),
-                size: (880, 580),
+                position: (10, 40),
+                size: (850, 550),
                 columns: vec![("L".to_string(), 128), ("Text".to_string(), 9999)],
                 resize_behavior: (Horz::Resize, Vert::Resize),
                 list_view_ex_style: LVS_EX::DOUBLEBUFFER | LVS_EX::FULLROWSELECT,
@@ -72,14 +200,55 @@ impl GorlMainWindow {
         let settings_lck = SETTINGS.read().unwrap();
         let highlight_settings = settings_lck.default_highlights.as_ref();
         let view = Rc::new(RwLock::new(None));
-        let search_window = SearchWindow::new(&wnd, transmitter.clone(), view.clone());
+        let excluded_lines = Rc::new(RwLock::new(None));
+        let timestamps = Rc::new(RwLock::new(None));
+        let search_window = SearchWindow::new(
+            &wnd,
+            transmitter.clone(),
+            view.clone(),
+            excluded_lines.clone(),
+            timestamps.clone(),
+        );
+        let minimap = Minimap::new(&wnd, (866, 40), (24, 550), transmitter.clone());
+        let rule_stats_panel = RuleStatsPanel::new(&wnd, transmitter.clone());
         let mut new_self = Self {
             wnd: wnd.clone(),
+            window_id: NEXT_WINDOW_ID.fetch_add(1, Ordering::Relaxed),
             list_view,
+            level_combo,
             view,
+            current_file: Rc::new(RwLock::new(None)),
+            row_mapping: Rc::new(RwLock::new(RowMapping::All)),
+            last_filter: Rc::new(RwLock::new(None)),
+            filter_description: Rc::new(RwLock::new(None)),
+            filter_active: Rc::new(RwLock::new(false)),
+            last_pipeline: Rc::new(RwLock::new(FilterPipeline::default())),
+            exclude_patterns: Rc::new(RwLock::new(
+                settings_lck.exclude_patterns.clone().unwrap_or_default(),
+            )),
+            excluded_lines,
             search_window,
+            minimap,
             inbox: inbox.clone(),
-            highlighter: Highlighter::new(highlight_settings.map_or(vec![], |a| a.clone())), //transmitter: transmitter.clone(),
+            transmitter: transmitter.clone(),
+            highlighter: Rc::new(RwLock::new(Highlighter::new(
+                highlight_settings.map_or(vec![], |a| a.clone()),
+            ))),
+            style_cache: Rc::new(RwLock::new(StyleCache::default())),
+            rule_stats_panel,
+            rule_stats_generation: Arc::new(AtomicU64::new(0)),
+            levels: Rc::new(RwLock::new(None)),
+            min_level: Rc::new(RwLock::new(None)),
+            level_lines: Rc::new(RwLock::new(None)),
+            timestamps,
+            time_range: Rc::new(RwLock::new(None)),
+            time_lines: Rc::new(RwLock::new(None)),
+            merged: Rc::new(RwLock::new(None)),
+            rotated: Rc::new(RwLock::new(None)),
+            profile: Rc::new(RwLock::new(None)),
+            manual_profile: Rc::new(RwLock::new(None)),
+            rules_edited: Rc::new(RwLock::new(false)),
+            styled_fonts: Rc::new(RwLock::new(HashMap::new())),
         };
 
         let wnd_copy = wnd.clone();
@@ -111,9 +280,9 @@ impl GorlMainWindow {
         new_self
     }
 
-    fn open_file(&self, path: &str) -> anyhow::Result<LineBasedFileView<File>> {
+    fn open_file(&self, path: &str) -> anyhow::Result<LineBasedFileView<LogReader>> {
         let bf = std::time::SystemTime::now();
-        let view = LineBasedFileView::new(File::open(path)?)?;
+        let view = LineBasedFileView::new(open_log(path)?)?;
         let now = std::time::SystemTime::now();
 
         if let Ok(elapsed) = now.duration_since(bf) {
@@ -127,17 +296,1358 @@ impl GorlMainWindow {
         Ok(view)
     }
 
+    /// Shows the file at `f` instead of the current one, resetting everything derived from it.
+    fn show_file(&self, f: &str) {
+        match self.open_file(f) {
+            Ok(view) => {
+                {
+                    *self.view.write().unwrap() = Some(view);
+                    *self.current_file.write().unwrap() = Some(f.to_owned());
+                    *self.row_mapping.write().unwrap() = RowMapping::All;
+                    *self.last_filter.write().unwrap() = None;
+                    *self.filter_description.write().unwrap() = None;
+                    *self.filter_active.write().unwrap() = false;
+                    *self.excluded_lines.write().unwrap() = None;
+                    self.style_cache.write().unwrap().clear();
+                    *self.levels.write().unwrap() = None;
+                    *self.level_lines.write().unwrap() = None;
+                    *self.timestamps.write().unwrap() = None;
+                    *self.time_range.write().unwrap() = None;
+                    *self.time_lines.write().unwrap() = None;
+                    *self.merged.write().unwrap() = None;
+                    *self.rotated.write().unwrap() = None;
+                }
+                self.list_view.items().set_count(
+                    self.view.read().unwrap().as_ref().unwrap().line_count() as u32,
+                    None,
+                );
+                self.rule_stats_panel.set_stats(None);
+                self.select_profile_for(f);
+                self.update_title();
+                info!(
+                    "set {f}. lines = {}",
+                    self.view.read().unwrap().as_ref().unwrap().line_count()
+                );
+                self.search_window.set_file(f);
+                self.search_window.set_time_range(None);
+                self.update_minimap();
+                self.register_open_file(f);
+                self.load_exclude_patterns();
+                self.update_excluded_lines();
+                self.update_levels();
+                self.update_timestamps();
+            }
+            Err(e) => {
+                error!("could not open {f}. ERR={:?}", e)
+            }
+        }
+    }
+
+    fn confirm_merge(&self, file_count: usize) -> bool {
+        matches!(
+            self.wnd.hwnd().MessageBox(
+                format!("Merge the {file_count} dropped files into one view ordered by time?")
+                    .as_str(),
+                "GORL - Merge files",
+                MB::YESNO | MB::ICONQUESTION,
+            ),
+            Ok(DLGID::YES)
+        )
+    }
+
+    /// Merges `files` by time on a background thread, see `merge_files`. The merged log is
+    /// shown once delivered as `MwMessage::FilesMerged`.
+    fn merge(&self, files: Vec<String>) {
+        let custom_format = SETTINGS.read().unwrap().timestamp_format.clone();
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            match merge_files(&files, custom_format.as_deref()) {
+                Ok(merged) => transmitter.send(MwMessage::FilesMerged(Arc::new(merged)))?,
+                Err(e) => error!("MAIN WINDOW: could not merge {files:?}: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    fn show_merged(&self, merged: Arc<MergedLog>) {
+        self.show_file(&merged.path);
+        if self.current_file.read().unwrap().as_ref() == Some(&merged.path) {
+            *self.merged.write().unwrap() = Some(merged);
+            self.update_title();
+            if self.load_exclude_patterns() {
+                self.update_excluded_lines();
+            }
+        }
+    }
+
+    /// Shows a dropped file, or the whole rotation set it belongs to if so wanted. A single
+    /// compressed file is always decompressed that way.
+    fn show_dropped(&self, f: &str) {
+        let members = rotation_set(f).unwrap_or_else(|e| {
+            error!("MAIN WINDOW: could not look for rotated files of {f}: {e}");
+            vec![]
+        });
+
+        if members.len() > 1 && self.confirm_rotation(f, members.len() - 1) {
+            self.open_rotation(members);
+        } else if f.ends_with(".gz") {
+            self.open_rotation(vec![f.to_owned()]);
+        } else {
+            self.show_file(f);
+        }
+    }
+
+    fn confirm_rotation(&self, f: &str, rotated_count: usize) -> bool {
+        matches!(
+            self.wnd.hwnd().MessageBox(
+                format!("Open {f} together with its {rotated_count} rotated files as one log?")
+                    .as_str(),
+                "GORL - Rotated logs",
+                MB::YESNO | MB::ICONQUESTION,
+            ),
+            Ok(DLGID::YES)
+        )
+    }
+
+    /// Asks for a glob like `C:\logs\app.log*` and opens the matching files as one log.
+    fn open_rotation_matching(&self) {
+        let pattern = match PromptDialog::show(
+            &self.wnd,
+            "GORL - Open rotated logs matching (e.g. C:\\logs\\app.log*)",
+            "",
+            false,
+        ) {
+            Some(pattern) => pattern,
+            None => return,
+        };
+
+        match rotation_set_matching(pattern.trim()) {
+            Ok(members) if !members.is_empty() => self.open_rotation(members),
+            Ok(_) => info!("MAIN WINDOW: no files match {pattern}"),
+            Err(e) => error!("MAIN WINDOW: could not list files matching {pattern}: {e}"),
+        }
+    }
+
+    /// Concatenates the `members` of a rotation set on a background thread, see
+    /// `concat_rotation`. The result is shown once delivered as
+    /// `MwMessage::RotationConcatenated`.
+    fn open_rotation(&self, members: Vec<String>) {
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            match concat_rotation(&members) {
+                Ok(rotated) => {
+                    transmitter.send(MwMessage::RotationConcatenated(Arc::new(rotated)))?
+                }
+                Err(e) => error!("MAIN WINDOW: could not open rotated logs {members:?}: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    fn show_rotated(&self, rotated: Arc<RotatedLog>) {
+        self.show_file(&rotated.path);
+        if self.current_file.read().unwrap().as_ref() == Some(&rotated.path) {
+            *self.rotated.write().unwrap() = Some(rotated);
+            self.update_title();
+            if self.load_exclude_patterns() {
+                self.update_excluded_lines();
+            }
+        }
+    }
+
+    /// Jumps from the focused line of a merged view or a rotation set to the line it came from:
+    /// in the window showing that file, or else by showing it in this window.
+    fn jump_to_source(&self) {
+        let focused = self.focused_line_number().saturating_sub(1);
+        let merged = self.merged.read().unwrap().clone();
+        let rotated = self.rotated.read().unwrap().clone();
+
+        let origin = if let Some(merged) = &merged {
+            merged
+                .origin(focused)
+                .map(|(source, line)| (merged.sources[source].clone(), line))
+        } else if let Some(rotated) = &rotated {
+            rotated
+                .member_of(focused)
+                .map(|(member, line)| (rotated.members[member].clone(), line))
+        } else {
+            None
+        };
+        let (path, line) = match origin {
+            Some(origin) => origin,
+            None => return,
+        };
+        if path.ends_with(".gz") {
+            info!("MAIN WINDOW: {path} is compressed, it can only be shown with its rotation set");
+            return;
+        }
+
+        let open = OPEN_FILES
+            .read()
+            .unwrap()
+            .iter()
+            .find(|open| open.path == path && open.window_id != self.window_id)
+            .cloned();
+        match open {
+            Some(open) => {
+                if let Err(e) = open.transmitter.send(MwMessage::JumpTo(line + 1)) {
+                    error!("MAIN WINDOW: could not jump to {path}: {e}");
+                }
+            }
+            None => {
+                self.show_file(&path);
+                self.jump_to(line + 1);
+            }
+        }
+    }
+
+    fn register_open_file(&self, path: &str) {
+        let mut open_files = OPEN_FILES.write().unwrap();
+        open_files.retain(|f| f.window_id != self.window_id);
+        open_files.push(OpenFile {
+            window_id: self.window_id,
+            path: path.to_owned(),
+            transmitter: self.transmitter.clone(),
+        });
+    }
+
     fn jump_to(&self, line: u64) {
         debug!("MAIN WINDOW: RECEIVED SEARCH RESULT SELECTED {line}");
 
+        let row = self.row_mapping.read().unwrap().row_of_line(line - 1);
+        self.select_row(row);
+
+        self.wnd.hwnd().SetForegroundWindow();
+    }
+
+    fn select_row(&self, row: u64) {
         self.list_view.items().select_all(false);
 
-        let item = self.list_view.items().get((line - 1) as u32);
+        let item = self.list_view.items().get(row as u32);
         item.ensure_visible();
         item.focus();
         item.select(true);
+    }
 
-        self.wnd.hwnd().SetForegroundWindow();
+    fn line_of_row(&self, row: u64) -> Option<u64> {
+        self.row_mapping.read().unwrap().line_of_row(row)
+    }
+
+    /// The 0-based file lines of the selected rows, merged into ranges of consecutive lines.
+    fn selected_line_ranges(&self) -> Vec<Range<u64>> {
+        let mut ranges: Vec<Range<u64>> = vec![];
+
+        for item in self.list_view.items().iter_selected() {
+            if let Some(line) = self.line_of_row(item.index() as u64) {
+                match ranges.last_mut() {
+                    Some(range) if range.end == line => range.end += 1,
+                    _ => ranges.push(line..line + 1),
+                }
+            }
+        }
+
+        ranges
+    }
+
+    /// Writes the selected lines to a file as they are, in a background thread.
+    fn export_selection(&self) {
+        let ranges = self.selected_line_ranges();
+        let path = match self.current_file.read().unwrap().clone() {
+            Some(path) if !ranges.is_empty() => path,
+            _ => return,
+        };
+
+        let slices: Vec<_> = match self.view.read().unwrap().as_ref() {
+            Some(view) => ranges.into_iter().filter_map(|r| view.slice(r)).collect(),
+            None => return,
+        };
+
+        let line_numbers = match self.wnd.hwnd().MessageBox(
+            "Prefix the exported lines with their line numbers?",
+            "GORL - Export selection",
+            MB::YESNOCANCEL | MB::ICONQUESTION,
+        ) {
+            Ok(DLGID::YES) => true,
+            Ok(DLGID::NO) => false,
+            _ => return,
+        };
+
+        let target = match crate::utils::ask_save_path(
+            self.wnd.hwnd(),
+            "selection.txt",
+            crate::utils::TEXT_FILE_TYPES,
+        ) {
+            Ok(Some(target)) => target,
+            Ok(None) => return,
+            Err(e) => {
+                error!("MAIN WINDOW: could not ask for export file: {e}");
+                return;
+            }
+        };
+
+        self.wnd.spawn_new_thread(move || {
+            let export = || -> anyhow::Result<u64> {
+                let mut file = open_log(&path)?;
+                let mut out = BufWriter::new(File::create(&target)?);
+                let mut written = 0;
+                for slice in &slices {
+                    written += copy_lines(&mut file, slice, &mut out, line_numbers)?;
+                }
+                out.flush()?;
+                Ok(written)
+            };
+
+            match export() {
+                Ok(written) => info!("MAIN WINDOW: exported {written} lines of {path} to {target}"),
+                Err(e) => error!("MAIN WINDOW: could not export to {target}: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    /// The text of the line shown in `row`, with its highlighting.
+    fn styled_row(&self, row: u64) -> Option<(String, LineStyle)> {
+        let line_index = self.line_of_row(row)?;
+        let (line, cached_lines) = {
+            let mut view = self.view.write().unwrap();
+            let view = view.as_mut()?;
+            let line = view.get_line(line_index).ok()?;
+            (line, view.cached_lines())
+        };
+
+        let style = self.style_cache.write().unwrap().get_or_insert_with(
+            cached_lines,
+            line_index,
+            || self.line_style(line_index, &line),
+        );
+        Some((line, style))
+    }
+
+    /// The highlighting of the 0-based `line_index`: that of the highlight rules, or else the
+    /// colours of its log level.
+    fn line_style(&self, line_index: u64, text: &str) -> LineStyle {
+        let mut style = self.highlighter.read().unwrap().style(text);
+
+        if style.line.is_none() && SETTINGS.read().unwrap().level_colors.unwrap_or(true) {
+            style.line = self
+                .levels
+                .read()
+                .unwrap()
+                .as_ref()
+                .and_then(|levels| levels.get(line_index))
+                .and_then(|level| level.colors());
+        }
+        style
+    }
+
+    /// The list font from the settings, varied by `text_style`.
+    fn create_font(text_style: TextStyle) -> anyhow::Result<winsafe::guard::DeleteObjectGuard<HFONT>> {
+        let settings = SETTINGS.read().unwrap();
+        let font = HFONT::CreateFont(
+            SIZE::new(0, settings.font.size),
+            0,
+            0,
+            if text_style.bold { FW::BOLD } else { FW::MEDIUM },
+            settings.font.italic || text_style.italic,
+            text_style.underline,
+            false,
+            CHARSET::DEFAULT,
+            OUT_PRECIS::DEFAULT,
+            CLIP::DEFAULT_PRECIS,
+            QUALITY::CLEARTYPE,
+            PITCH::FIXED,
+            settings.font.name.as_str(),
+        )?;
+        Ok(font)
+    }
+
+    /// Selects the list font varied by `text_style` into `hdc`, creating it on first use. The
+    /// font stays selected; the list view restores its own after drawing the item.
+    fn select_styled_font(&self, hdc: &HDC, text_style: TextStyle) -> anyhow::Result<()> {
+        let mut fonts = self.styled_fonts.write().unwrap();
+        if !fonts.contains_key(&text_style) {
+            let mut font = Self::create_font(text_style)?;
+            fonts.insert(text_style, font.leak());
+        }
+
+        if let Some(font) = fonts.get(&text_style) {
+            let _ = hdc.SelectObject(font)?.leak();
+        }
+        Ok(())
+    }
+
+    /// Draws the text column of `row` piece by piece in the colours of the highlight spans.
+    fn draw_styled_text(&self, hdc: &HDC, row: u32, text: &str, style: &LineStyle) -> anyhow::Result<()> {
+        let mut rc = RECT::default();
+        self.list_view.hwnd().SendMessage(lvm::GetSubItemRect {
+            item_index: row,
+            subitem_index: 1,
+            rect: &mut rc,
+            portion: co::LVIR::BOUNDS,
+        })?;
+
+        let color = |c: (u8, u8, u8)| COLORREF::new(c.0, c.1, c.2);
+        let (row_fg, row_bg) = match style.line {
+            Some(line) => (color(line.fg_color), color(line.bg_color)),
+            None => (
+                winsafe::GetSysColor(co::COLOR::WINDOWTEXT),
+                winsafe::GetSysColor(co::COLOR::WINDOW),
+            ),
+        };
+
+        let brush = HBRUSH::CreateSolidBrush(row_bg)?;
+        hdc.FillRect(rc, &brush)?;
+        hdc.SetBkMode(co::BKMODE::OPAQUE)?;
+
+        // same padding as the list view uses for its labels
+        let mut x = rc.left + 6;
+        for (range, colors) in style.segments(text.len()) {
+            if x >= rc.right {
+                break;
+            }
+            let piece = match text.get(range) {
+                Some(piece) => piece.replace('\t', "    "),
+                None => continue,
+            };
+
+            let (fg, bg) = colors.map_or((row_fg, row_bg), |c| (color(c.fg_color), color(c.bg_color)));
+            hdc.SetTextColor(fg)?;
+            hdc.SetBkColor(bg)?;
+            self.select_styled_font(hdc, colors.map_or(TextStyle::default(), |c| c.text_style))?;
+
+            let piece_rc = RECT {
+                left: x,
+                ..rc
+            };
+            hdc.DrawText(
+                &piece,
+                &piece_rc,
+                co::DT::SINGLELINE | co::DT::VCENTER | co::DT::NOPREFIX | co::DT::LEFT,
+            )?;
+            x += hdc.GetTextExtentPoint32(&piece)?.cx;
+        }
+
+        Ok(())
+    }
+
+    /// The selected lines with their highlight colours and the hits of the last search.
+    fn styled_selection(&self) -> Vec<StyledLine> {
+        let hit_matcher = self.search_window.last_hit_matcher();
+        let mut view = self.view.write().unwrap();
+        let view = match view.as_mut() {
+            Some(view) => view,
+            None => return vec![],
+        };
+
+        self.list_view
+            .items()
+            .iter_selected()
+            .filter_map(|item| self.line_of_row(item.index() as u64))
+            .filter_map(|line| {
+                let text = view.get_line(line).ok()?;
+                Some(StyledLine {
+                    number: line + 1,
+                    style: self.line_style(line, &text),
+                    hits: hit_matcher.as_ref().map_or(vec![], |m| m.spans(&text)),
+                    text,
+                })
+            })
+            .collect()
+    }
+
+    /// Writes the selected lines as HTML or RTF, depending on the chosen file type.
+    fn export_styled_selection(&self) {
+        let lines = self.styled_selection();
+        if lines.is_empty() {
+            return;
+        }
+
+        let target = match crate::utils::ask_save_path(
+            self.wnd.hwnd(),
+            "selection.html",
+            &[("HTML files", "*.html;*.htm"), ("Rich text files", "*.rtf")],
+        ) {
+            Ok(Some(target)) => target,
+            Ok(None) => return,
+            Err(e) => {
+                error!("MAIN WINDOW: could not ask for export file: {e}");
+                return;
+            }
+        };
+
+        let content = if target.to_lowercase().ends_with(".rtf") {
+            render_rtf(&lines, true)
+        } else {
+            render_html(&lines, true)
+        };
+
+        match std::fs::write(&target, content) {
+            Ok(_) => info!("MAIN WINDOW: exported {} lines to {target}", lines.len()),
+            Err(e) => error!("MAIN WINDOW: could not export to {target}: {e}"),
+        }
+    }
+
+    /// Limits the search window to the lines spanned by the current selection.
+    fn search_in_selection(&self) {
+        let items = self.list_view.items();
+        let mut selected = items.iter_selected().map(|item| item.index() as u64);
+
+        if let Some(first) = selected.next() {
+            let last = selected.last().unwrap_or(first);
+            if let (Some(first), Some(last)) = (self.line_of_row(first), self.line_of_row(last)) {
+                self.search_window.set_line_range(first + 1, last + 1);
+            }
+        }
+    }
+
+    /// Switches the rows shown in the list view, keeping the cursor on the same file line.
+    fn set_row_mapping(&self, mapping: RowMapping) {
+        let focused_line = self
+            .list_view
+            .items()
+            .focused()
+            .and_then(|item| self.line_of_row(item.index() as u64));
+
+        let line_count = match self.view.read().unwrap().as_ref() {
+            Some(view) => view.line_count(),
+            None => return,
+        };
+
+        let row_count = mapping.row_count(line_count);
+        *self.row_mapping.write().unwrap() = mapping;
+
+        self.list_view.items().set_count(row_count as u32, None);
+        self.update_title();
+
+        if let Some(line) = focused_line {
+            let row = self.row_mapping.read().unwrap().row_of_line(line);
+            if row < row_count {
+                self.select_row(row);
+            }
+        }
+    }
+
+    /// Combines the active filter and the excluded lines into the rows to show.
+    fn compute_row_mapping(&self) -> RowMapping {
+        let filter = if *self.filter_active.read().unwrap() {
+            self.last_filter.read().unwrap().clone()
+        } else {
+            None
+        };
+
+        let mut filter = filter.unwrap_or(RowMapping::All);
+        if let Some(lines) = self.level_lines.read().unwrap().as_ref() {
+            filter = filter.restricted_to(lines);
+        }
+        if let Some(lines) = self.time_lines.read().unwrap().clone() {
+            filter = filter.restricted_to_range(lines);
+        }
+
+        match self.excluded_lines.read().unwrap().as_ref() {
+            Some(excluded) => filter.without(excluded),
+            None => filter,
+        }
+    }
+
+    fn refresh_row_mapping(&self) {
+        self.set_row_mapping(self.compute_row_mapping());
+    }
+
+    /// Detects the log level of every line on a background thread. The result is delivered as
+    /// `MwMessage::LevelsDetected`.
+    fn update_levels(&self) {
+        let file = match self.current_file.read().unwrap().clone() {
+            Some(file) => file,
+            None => return,
+        };
+
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            match LevelIndex::build(&file) {
+                Ok(levels) => transmitter.send(MwMessage::LevelsDetected {
+                    file,
+                    levels: Arc::new(levels),
+                })?,
+                Err(e) => error!("MAIN WINDOW: could not detect log levels: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    fn set_levels(&self, file: String, levels: Arc<LevelIndex>) {
+        if self.current_file.read().unwrap().as_ref() != Some(&file) {
+            debug!("MAIN WINDOW: dropping outdated log levels for {file}");
+            return;
+        }
+
+        *self.levels.write().unwrap() = Some(levels);
+        self.style_cache.write().unwrap().clear();
+        self.update_level_lines();
+
+        if let Err(e) = self.list_view.hwnd().InvalidateRect(None, true) {
+            error!("MAIN WINDOW: could not repaint after detecting log levels: {e}");
+        }
+    }
+
+    /// Indexes the timestamps of the lines on a background thread, page by page like the view.
+    /// The result is delivered as `MwMessage::TimestampsIndexed`.
+    fn update_timestamps(&self) {
+        let file = match self.current_file.read().unwrap().clone() {
+            Some(file) => file,
+            None => return,
+        };
+        let lines_per_page = match self.view.read().unwrap().as_ref() {
+            Some(view) => view.lines_per_page(),
+            None => return,
+        };
+        let custom_format = SETTINGS.read().unwrap().timestamp_format.clone();
+
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            match TimestampIndex::build(&file, lines_per_page, custom_format.as_deref()) {
+                Ok(Some(timestamps)) => transmitter.send(MwMessage::TimestampsIndexed {
+                    file,
+                    timestamps: Arc::new(timestamps),
+                })?,
+                Ok(None) => info!("MAIN WINDOW: no timestamps found in {file}"),
+                Err(e) => error!("MAIN WINDOW: could not index timestamps: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    fn set_timestamps(&self, file: String, timestamps: Arc<TimestampIndex>) {
+        if self.current_file.read().unwrap().as_ref() != Some(&file) {
+            debug!("MAIN WINDOW: dropping outdated timestamps for {file}");
+            return;
+        }
+
+        if let Some((first, last)) = timestamps.range() {
+            info!(
+                "MAIN WINDOW: {file} has {:?} timestamps from {} to {}",
+                timestamps.format,
+                format_millis(first, timestamps.offset),
+                format_millis(last, timestamps.offset)
+            );
+        }
+        *self.timestamps.write().unwrap() = Some(timestamps);
+        self.update_minimap();
+    }
+
+    /// Asks for a time and jumps to the first line at or after it, see
+    /// `TimestampIndex::find_line`.
+    fn go_to_time(&self) {
+        let timestamps = match self.timestamps.read().unwrap().clone() {
+            Some(timestamps) => timestamps,
+            None => {
+                info!("MAIN WINDOW: no timestamps known to go to");
+                return;
+            }
+        };
+        let range = match timestamps.range() {
+            Some(range) => range,
+            None => return,
+        };
+
+        let text = match PromptDialog::show(
+            &self.wnd,
+            "GORL - Go to time (e.g. 14:32:10 or 2023-10-11 14:32:10)",
+            &format_millis(range.0, timestamps.offset),
+            false,
+        ) {
+            Some(text) => text,
+            None => return,
+        };
+
+        let target = match parse_user_time(&text, &timestamps.format, range, timestamps.offset) {
+            Some(target) => target,
+            None => {
+                error!("MAIN WINDOW: invalid time to go to: {text}");
+                return;
+            }
+        };
+
+        let line = match self.view.write().unwrap().as_mut() {
+            Some(view) => timestamps.line_at_or_after(target, view),
+            None => return,
+        };
+
+        match line {
+            Some(line) => {
+                info!(
+                    "MAIN WINDOW: going to {} at line {}",
+                    format_millis(target, timestamps.offset),
+                    line + 1
+                );
+                self.jump_to(line + 1);
+            }
+            None => info!(
+                "MAIN WINDOW: no line at or after {}",
+                format_millis(target, timestamps.offset)
+            ),
+        }
+    }
+
+    /// Asks for a time range like `from 14:30 to 14:45` and shows only the lines within it. The
+    /// search window is limited to the same range. An empty range shows all lines again.
+    fn filter_time_range(&self) {
+        let timestamps = match self.timestamps.read().unwrap().clone() {
+            Some(timestamps) => timestamps,
+            None => {
+                info!("MAIN WINDOW: no timestamps known to filter by");
+                return;
+            }
+        };
+        let range = match timestamps.range() {
+            Some(range) => range,
+            None => return,
+        };
+
+        let current = self
+            .time_range
+            .read()
+            .unwrap()
+            .unwrap_or(TimeRange {
+                from: range.0,
+                to: range.1,
+                offset: timestamps.offset,
+            })
+            .to_string();
+        let text = match PromptDialog::show(
+            &self.wnd,
+            "GORL - Show lines from <time> to <time> (empty for all)",
+            &current,
+            false,
+        ) {
+            Some(text) => text,
+            None => return,
+        };
+
+        if text.trim().is_empty() {
+            *self.time_range.write().unwrap() = None;
+            *self.time_lines.write().unwrap() = None;
+            self.search_window.set_time_range(None);
+            self.refresh_row_mapping();
+            return;
+        }
+
+        let time_range = match TimeRange::parse(&text, &timestamps.format, range, timestamps.offset)
+        {
+            Some(time_range) => time_range,
+            None => {
+                error!("MAIN WINDOW: invalid time range: {text}");
+                return;
+            }
+        };
+
+        let lines = match self.view.write().unwrap().as_mut() {
+            Some(view) => timestamps.lines_in(&time_range, view),
+            None => return,
+        };
+        info!("MAIN WINDOW: {time_range} covers lines {lines:?}");
+
+        *self.time_range.write().unwrap() = Some(time_range);
+        *self.time_lines.write().unwrap() = Some(lines);
+        self.search_window.set_time_range(Some(&time_range));
+        self.refresh_row_mapping();
+    }
+
+    /// Shows only lines of level `min_level` or above, or all lines for `None`.
+    fn set_min_level(&self, min_level: Option<LogLevel>) {
+        info!("MAIN WINDOW: showing lines of level {min_level:?} and above");
+        *self.min_level.write().unwrap() = min_level;
+        self.update_level_lines();
+    }
+
+    /// Collects the lines of the minimum level or above on a background thread. The result is
+    /// delivered as `MwMessage::LevelLines`.
+    fn update_level_lines(&self) {
+        let min_level = *self.min_level.read().unwrap();
+        let levels = self.levels.read().unwrap().clone();
+
+        let (min_level, levels) = match (min_level, levels) {
+            (Some(min_level), Some(levels)) => (min_level, levels),
+            _ => {
+                if self.level_lines.write().unwrap().take().is_some() {
+                    self.refresh_row_mapping();
+                }
+                return;
+            }
+        };
+
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            let lines = Arc::new(levels.lines_at_least(min_level));
+            transmitter.send(MwMessage::LevelLines {
+                levels,
+                min_level,
+                lines,
+            })?;
+            Ok(())
+        });
+    }
+
+    fn set_level_lines(
+        &self,
+        levels: Arc<LevelIndex>,
+        min_level: LogLevel,
+        lines: Arc<CompressedSearchResults>,
+    ) {
+        let is_current = self
+            .levels
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, &levels))
+            && *self.min_level.read().unwrap() == Some(min_level);
+
+        if !is_current {
+            debug!("MAIN WINDOW: dropping outdated lines of level {min_level:?}");
+            return;
+        }
+
+        *self.level_lines.write().unwrap() = Some(lines);
+        self.refresh_row_mapping();
+    }
+
+    /// Toggles between the full file and the last applied filter.
+    fn toggle_filter(&self) {
+        if self.last_filter.read().unwrap().is_none() {
+            return;
+        }
+
+        {
+            let mut active = self.filter_active.write().unwrap();
+            *active = !*active;
+        }
+        self.refresh_row_mapping();
+    }
+
+    fn show_filtered(&self, rows: RowMapping, description: Option<String>) {
+        *self.last_filter.write().unwrap() = Some(rows);
+        *self.filter_description.write().unwrap() = description;
+        *self.filter_active.write().unwrap() = true;
+        self.refresh_row_mapping();
+    }
+
+    /// Lets the user edit the filter pipeline of this window and runs it in the background.
+    /// The result is delivered as `MwMessage::PipelineApplied`.
+    fn edit_pipeline(&self) {
+        let (file, line_count) = match (
+            self.current_file.read().unwrap().clone(),
+            self.view.read().unwrap().as_ref(),
+        ) {
+            (Some(file), Some(view)) => (file, view.line_count()),
+            _ => return,
+        };
+
+        let current = self.last_pipeline.read().unwrap().to_string();
+        let text = match PromptDialog::show(
+            &self.wnd,
+            "GORL - Filter pipeline (include/exclude <regex>, include time between <time> and <time>, use <saved filter>)",
+            &current,
+            true,
+        ) {
+            Some(text) => text,
+            None => return,
+        };
+
+        let saved = SETTINGS.read().unwrap().saved_filters.clone().unwrap_or_default();
+        let pipeline = match FilterPipeline::parse(&text, &saved) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                error!("MAIN WINDOW: invalid filter pipeline: {e}");
+                return;
+            }
+        };
+
+        *self.last_pipeline.write().unwrap() = pipeline.clone();
+
+        let timestamps = self.timestamps.read().unwrap().clone();
+        let time_lines = match self.view.write().unwrap().as_mut() {
+            Some(view) => pipeline.time_lines(timestamps.as_deref(), view),
+            None => return,
+        };
+        let time_lines = match time_lines {
+            Ok(time_lines) => time_lines,
+            Err(e) => {
+                error!("MAIN WINDOW: invalid filter pipeline: {e}");
+                return;
+            }
+        };
+
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            match pipeline.run(&file, line_count, &time_lines) {
+                Ok(result) => transmitter.send(MwMessage::PipelineApplied { file, result })?,
+                Err(e) => error!("MAIN WINDOW: could not run filter pipeline: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    /// Saves the last pipeline of this window under a name, so it can be reused with `use <name>`.
+    fn save_pipeline(&self) {
+        let pipeline = self.last_pipeline.read().unwrap().clone();
+        if pipeline.stages.is_empty() {
+            return;
+        }
+
+        if let Some(name) = PromptDialog::show(&self.wnd, "GORL - Save filter as", "", false) {
+            let name = name.trim().to_owned();
+            if name.is_empty() {
+                return;
+            }
+
+            if let Ok(mut settings) = SETTINGS.write() {
+                let saved = settings.saved_filters.get_or_insert_with(Vec::new);
+                saved.retain(|p| p.name != name);
+                saved.push(NamedFilterPipeline {
+                    name,
+                    stages: pipeline.stages,
+                });
+
+                if let Err(e) = settings.save_key("saved_filters", &settings.saved_filters) {
+                    error!("MAIN WINDOW: could not save filter: {e}");
+                }
+            }
+        }
+    }
+
+    fn set_pipeline_result(&self, file: String, result: PipelineResult) {
+        if self.current_file.read().unwrap().as_ref() != Some(&file) {
+            debug!("MAIN WINDOW: dropping outdated pipeline result for {file}");
+            return;
+        }
+
+        let line_count = self
+            .view
+            .read()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |view| view.line_count());
+
+        self.show_filtered(result.rows.clone(), Some(result.describe(line_count)));
+    }
+
+    /// Replaces the highlight rules of this window and repaints it.
+    fn set_highlight_rules(&self, rules: Vec<HighlightSetting>) {
+        *self.highlighter.write().unwrap() = Highlighter::new(rules);
+        self.style_cache.write().unwrap().clear();
+        self.update_rule_stats();
+
+        if let Err(e) = self.list_view.hwnd().InvalidateRect(None, true) {
+            error!("MAIN WINDOW: could not repaint after changing highlight rules: {e}");
+        }
+    }
+
+    fn use_profile(&self, name: Option<String>, rules: Vec<HighlightSetting>) {
+        *self.profile.write().unwrap() = name;
+        self.set_highlight_rules(rules);
+        self.update_title();
+    }
+
+    /// Picks the highlight profile matching the file just opened, falling back to the default
+    /// rules. Does nothing if a profile was chosen or the rules were edited by hand.
+    fn select_profile_for(&self, path: &str) {
+        if self.manual_profile.read().unwrap().is_some() || *self.rules_edited.read().unwrap() {
+            // same rules, but they still have to be counted in the new file
+            self.update_rule_stats();
+            return;
+        }
+
+        let first_lines: Vec<String> = match self.view.write().unwrap().as_mut() {
+            Some(view) => (0..view.line_count().min(DETECT_LINES as u64))
+                .filter_map(|line| view.get_line(line).ok())
+                .collect(),
+            None => vec![],
+        };
+
+        let (profiles, default_rules) = {
+            let settings = SETTINGS.read().unwrap();
+            (
+                settings.highlight_profiles.clone().unwrap_or_default(),
+                settings.default_highlights.clone().unwrap_or_default(),
+            )
+        };
+
+        match HighlightProfile::select(&profiles, path, &first_lines) {
+            Some(profile) => {
+                info!("MAIN WINDOW: using highlight profile {} for {path}", profile.name);
+                self.use_profile(Some(profile.name.clone()), profile.rules.clone());
+            }
+            None => self.use_profile(None, default_rules),
+        }
+    }
+
+    /// Lets the user choose the highlight profile of this window, or go back to picking it
+    /// automatically.
+    fn choose_profile(&self) {
+        let profiles = SETTINGS
+            .read()
+            .unwrap()
+            .highlight_profiles
+            .clone()
+            .unwrap_or_default();
+
+        let mut items = vec!["Automatic".to_owned()];
+        items.extend(profiles.iter().map(|p| p.name.clone()));
+
+        let manual = self.manual_profile.read().unwrap().clone();
+        let selected = manual
+            .and_then(|name| profiles.iter().position(|p| p.name == name))
+            .map_or(0, |idx| idx + 1);
+
+        let choice = ChoiceDialog::show(&self.wnd, "GORL - Highlight profile", &items, selected);
+        if choice.is_some() {
+            // a chosen profile replaces rules edited by hand
+            *self.rules_edited.write().unwrap() = false;
+        }
+
+        match choice {
+            Some(0) => {
+                *self.manual_profile.write().unwrap() = None;
+                let file = self.current_file.read().unwrap().clone();
+                match file {
+                    Some(file) => self.select_profile_for(&file),
+                    None => {
+                        let rules = SETTINGS.read().unwrap().default_highlights.clone();
+                        self.use_profile(None, rules.unwrap_or_default());
+                    }
+                }
+            }
+            Some(idx) => {
+                if let Some(profile) = profiles.get(idx - 1) {
+                    *self.manual_profile.write().unwrap() = Some(profile.name.clone());
+                    self.use_profile(Some(profile.name.clone()), profile.rules.clone());
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Counts the lines matched by each highlight rule on a background thread. The result is
+    /// delivered as `MwMessage::RuleStats`.
+    fn update_rule_stats(&self) {
+        let generation = self.rule_stats_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let file = match self.current_file.read().unwrap().clone() {
+            Some(file) => file,
+            None => return,
+        };
+
+        let highlighter = self.highlighter.read().unwrap().clone();
+        let current_generation = self.rule_stats_generation.clone();
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            match count_rule_hits(&highlighter, &file, &current_generation, generation) {
+                Ok(Some(stats)) => transmitter.send(MwMessage::RuleStats {
+                    file,
+                    generation,
+                    stats: Arc::new(stats),
+                })?,
+                Ok(None) => {}
+                Err(e) => error!("MAIN WINDOW: could not count highlight rule hits: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    fn set_rule_stats(&self, file: String, generation: u64, stats: Arc<RuleStats>) {
+        let is_current = self.current_file.read().unwrap().as_ref() == Some(&file)
+            && self.rule_stats_generation.load(Ordering::Relaxed) == generation;
+
+        if !is_current {
+            debug!("MAIN WINDOW: dropping outdated highlight rule hits for {file}");
+            return;
+        }
+
+        self.rule_stats_panel.set_stats(Some(stats));
+    }
+
+    /// Lets the user edit the highlight rules of this window, repainting on every change.
+    fn edit_highlight_rules(&self) {
+        let original = self.highlighter.read().unwrap().rules().to_vec();
+
+        let edited = RuleEditorDialog::show(&self.wnd, original.clone(), {
+            let myself = self.clone();
+            move |rules| myself.set_highlight_rules(rules.to_vec())
+        });
+
+        match edited {
+            Some(rules) => {
+                info!("MAIN WINDOW: now using {} highlight rules", rules.len());
+                if rules != original {
+                    *self.rules_edited.write().unwrap() = true;
+                    self.update_title();
+                }
+            }
+            None => self.set_highlight_rules(original),
+        }
+    }
+
+    /// The file the exclude patterns of this window are saved for: the newest member of a
+    /// rotation set, the sources of a merged log, or else the current file.
+    fn exclude_patterns_key(&self) -> Option<String> {
+        if let Some(rotated) = self.rotated.read().unwrap().as_ref() {
+            return rotated.members.last().cloned();
+        }
+        if let Some(merged) = self.merged.read().unwrap().as_ref() {
+            return Some(merged.sources.join(" + "));
+        }
+        self.current_file.read().unwrap().clone()
+    }
+
+    /// Takes the exclude patterns saved for the shown file, or the default `exclude_patterns`
+    /// of the settings if there are none. Returns `true` if they changed.
+    fn load_exclude_patterns(&self) -> bool {
+        let key = self.exclude_patterns_key();
+        let patterns = {
+            let settings = SETTINGS.read().unwrap();
+            settings
+                .file_exclude_patterns
+                .iter()
+                .flatten()
+                .find(|saved| Some(&saved.file) == key.as_ref())
+                .map(|saved| saved.patterns.clone())
+                .or_else(|| settings.exclude_patterns.clone())
+                .unwrap_or_default()
+        };
+
+        let mut current = self.exclude_patterns.write().unwrap();
+        let changed = *current != patterns;
+        *current = patterns;
+        changed
+    }
+
+    /// Lets the user edit the exclude patterns of this window (one regex per line). They are
+    /// saved for the shown file, see `exclude_patterns_key`, and used again whenever it is shown.
+    fn edit_exclude_patterns(&self) {
+        let current = self.exclude_patterns.read().unwrap().join("\n");
+
+        if let Some(text) =
+            PromptDialog::show(&self.wnd, "GORL - Exclude lines matching", &current, true)
+        {
+            let patterns: Vec<String> = text
+                .lines()
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_owned)
+                .collect();
+
+            *self.exclude_patterns.write().unwrap() = patterns.clone();
+            self.update_excluded_lines();
+
+            let file = match self.exclude_patterns_key() {
+                Some(file) => file,
+                None => return,
+            };
+            if let Ok(mut settings) = SETTINGS.write() {
+                let saved = settings.file_exclude_patterns.get_or_insert_with(Vec::new);
+                saved.retain(|saved| saved.file != file);
+                saved.push(FileExcludePatterns { file, patterns });
+
+                if let Err(e) =
+                    settings.save_key("file_exclude_patterns", &settings.file_exclude_patterns)
+                {
+                    error!("MAIN WINDOW: could not save exclude patterns: {e}");
+                }
+            }
+        }
+    }
+
+    /// Searches the lines matching any exclude pattern on a background thread. The result is
+    /// delivered as `MwMessage::ExcludedLines`.
+    fn update_excluded_lines(&self) {
+        let patterns = self.exclude_patterns.read().unwrap().clone();
+        let file = match self.current_file.read().unwrap().clone() {
+            Some(file) => file,
+            None => return,
+        };
+
+        if patterns.is_empty() {
+            if self.excluded_lines.write().unwrap().take().is_some() {
+                self.refresh_row_mapping();
+                self.search_window.rerun_last_search();
+            }
+            return;
+        }
+
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            let query = patterns
+                .iter()
+                .map(|p| format!("(?:{p})"))
+                .collect::<Vec<_>>()
+                .join("|");
+
+            match search_in_file(&query, &file) {
+                Ok(lines) => transmitter.send(MwMessage::ExcludedLines {
+                    file,
+                    patterns,
+                    lines: Arc::new(lines),
+                })?,
+                Err(e) => error!("MAIN WINDOW: could not search excluded lines: {e}"),
+            }
+
+            Ok(())
+        });
+    }
+
+    fn set_excluded_lines(
+        &self,
+        file: String,
+        patterns: Vec<String>,
+        lines: Arc<CompressedSearchResults>,
+    ) {
+        let is_current = self.current_file.read().unwrap().as_ref() == Some(&file)
+            && *self.exclude_patterns.read().unwrap() == patterns;
+
+        if !is_current {
+            debug!("MAIN WINDOW: dropping outdated excluded lines for {file}");
+            return;
+        }
+
+        info!("MAIN WINDOW: hiding {} lines of {file}", lines.get_count());
+        *self.excluded_lines.write().unwrap() = Some(lines);
+        self.refresh_row_mapping();
+        // the results of the active search must not show the newly excluded lines either
+        self.search_window.rerun_last_search();
+    }
+
+    fn update_title(&self) {
+        if let Some(file) = self.current_file.read().unwrap().as_ref() {
+            let mut title = format!("GORL - {file}");
+
+            let line_count = self
+                .view
+                .read()
+                .unwrap()
+                .as_ref()
+                .map_or(0, |view| view.line_count());
+
+            let mapping = self.row_mapping.read().unwrap();
+            if !matches!(*mapping, RowMapping::All) {
+                title.push_str(
+                    format!(
+                        " [SHOWING: {} of {line_count} lines]",
+                        mapping.row_count(line_count)
+                    )
+                    .as_str(),
+                );
+            }
+
+            if *self.filter_active.read().unwrap() {
+                if let Some(description) = self.filter_description.read().unwrap().as_ref() {
+                    title.push_str(format!(" [PIPELINE: {description}]").as_str());
+                }
+            }
+
+            if let Some(excluded) = self.excluded_lines.read().unwrap().as_ref() {
+                title.push_str(format!(" [HIDDEN: {} lines]", excluded.get_count()).as_str());
+            }
+
+            if self.level_lines.read().unwrap().is_some() {
+                if let Some(level) = self.min_level.read().unwrap().as_ref() {
+                    title.push_str(format!(" [LEVEL: {}+]", level.label()).as_str());
+                }
+            }
+
+            if let Some(merged) = self.merged.read().unwrap().as_ref() {
+                let tags: Vec<&str> = (0..merged.sources.len()).map(|s| merged.tag(s)).collect();
+                title.push_str(format!(" [MERGED: {}]", tags.join(" + ")).as_str());
+            }
+
+            if let Some(rotated) = self.rotated.read().unwrap().as_ref() {
+                let name = |path: &String| {
+                    Path::new(path)
+                        .file_name()
+                        .map_or_else(|| path.clone(), |name| name.to_string_lossy().into_owned())
+                };
+                if let (Some(oldest), Some(newest)) = (rotated.members.first(), rotated.members.last()) {
+                    title.push_str(
+                        format!(
+                            " [ROTATED: {} .. {}, {} files]",
+                            name(oldest),
+                            name(newest),
+                            rotated.members.len()
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+
+            if let Some(time_range) = self.time_range.read().unwrap().as_ref() {
+                title.push_str(format!(" [TIME: {time_range}]").as_str());
+            }
+
+            if *self.rules_edited.read().unwrap() {
+                title.push_str(" [RULES: edited]");
+            } else if let Some(profile) = self.profile.read().unwrap().as_ref() {
+                if self.manual_profile.read().unwrap().is_some() {
+                    title.push_str(format!(" [PROFILE: {profile} (manual)]").as_str());
+                } else {
+                    title.push_str(format!(" [PROFILE: {profile}]").as_str());
+                }
+            }
+
+            self.wnd.set_text(title.as_str());
+        }
+    }
+
+    /// Shows the hits of the last search in the current file on the minimap.
+    fn update_minimap(&self) {
+        let results = match self.current_file.read().unwrap().as_ref() {
+            Some(file) => self.search_window.results_for(file),
+            None => None,
+        };
+        let line_count = self
+            .view
+            .read()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |view| view.line_count());
+
+        self.minimap.set_timestamps(self.timestamps.read().unwrap().clone());
+        self.minimap.set_results(results, line_count);
+    }
+
+    /// The 1-based number of the focused line, or 0 if none is.
+    fn focused_line_number(&self) -> u64 {
+        // search results are 1-based line numbers, list view items 0-based
+        self.list_view
+            .items()
+            .focused()
+            .and_then(|item| self.line_of_row(item.index() as u64))
+            .map_or(0, |line| line + 1)
+    }
+
+    /// Jumps to the next (or previous) search result relative to the focused line.
+    fn jump_to_match(&self, forward: bool) {
+        let results = match self.current_file.read().unwrap().as_ref() {
+            Some(file) => self.search_window.results_for(file),
+            None => None,
+        };
+
+        let current = self.focused_line_number();
+
+        let target = results.and_then(|results| {
+            if forward {
+                results.next_after(current)
+            } else {
+                results.previous_before(current)
+            }
+        });
+
+        if let Some(line) = target {
+            self.jump_to(line);
+        }
     }
 
     fn handle(&self, msg: MwMessage) {
@@ -145,6 +1655,41 @@ impl GorlMainWindow {
 
         match msg {
             MwMessage::JumpTo(line) => self.jump_to(line),
+            MwMessage::ShowFiltered(lines) => self.show_filtered(RowMapping::Only(lines), None),
+            MwMessage::ExcludedLines {
+                file,
+                patterns,
+                lines,
+            } => self.set_excluded_lines(file, patterns, lines),
+            MwMessage::PipelineApplied { file, result } => self.set_pipeline_result(file, result),
+            MwMessage::SearchDone(finished) => self.search_window.show_search(&finished),
+            MwMessage::SearchFinished => self.update_minimap(),
+            MwMessage::JumpToNextHit { lines, first } => {
+                let target = if first {
+                    lines.get(0)
+                } else {
+                    lines.next_after(self.focused_line_number())
+                };
+                if let Some(line) = target {
+                    self.jump_to(line);
+                }
+            }
+            MwMessage::RuleStats {
+                file,
+                generation,
+                stats,
+            } => self.set_rule_stats(file, generation, stats),
+            MwMessage::LevelsDetected { file, levels } => self.set_levels(file, levels),
+            MwMessage::LevelLines {
+                levels,
+                min_level,
+                lines,
+            } => self.set_level_lines(levels, min_level, lines),
+            MwMessage::TimestampsIndexed { file, timestamps } => {
+                self.set_timestamps(file, timestamps)
+            }
+            MwMessage::FilesMerged(merged) => self.show_merged(merged),
+            MwMessage::RotationConcatenated(rotated) => self.show_rotated(rotated),
         }
     }
 
@@ -180,7 +1725,20 @@ impl GorlMainWindow {
                             str_to_cpy.push_str("\r\n"); // Windows wants CRLF :(
                         }
 
-                        match crate::utils::copy_text_to_clipboard(&h_wnd, str_to_cpy.as_str()) {
+                        let copied = if is_shift_down {
+                            // also keep the colours for pasting into rich text editors
+                            let lines = (*ptr).styled_selection();
+                            crate::utils::copy_rich_text_to_clipboard(
+                                &h_wnd,
+                                str_to_cpy.as_str(),
+                                &render_html(&lines, true),
+                                &render_rtf(&lines, true),
+                            )
+                        } else {
+                            crate::utils::copy_text_to_clipboard(&h_wnd, str_to_cpy.as_str())
+                        };
+
+                        match copied {
                             Ok(_) => {
                                 info!(
                                     "subclass_list_view::SubClassProcedure: clipboard data has been set!"
@@ -193,6 +1751,73 @@ impl GorlMainWindow {
                             }
                         }
                     }
+                } else if VK::from_raw(w_param as u16) == VK::F3 {
+                    let ptr = dw_ref_data as *const Self;
+                    (*ptr).jump_to_match(!winsafe::GetAsyncKeyState(VK::SHIFT));
+                } else if VK::from_raw(w_param as u16) == VK::F4 {
+                    let ptr = dw_ref_data as *const Self;
+                    (*ptr).toggle_filter();
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_S
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    if winsafe::GetAsyncKeyState(VK::SHIFT) {
+                        (*ptr).export_styled_selection();
+                    } else {
+                        (*ptr).export_selection();
+                    }
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_R
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    (*ptr).search_in_selection();
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_H
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    if winsafe::GetAsyncKeyState(VK::SHIFT) {
+                        (*ptr).choose_profile();
+                    } else {
+                        (*ptr).edit_highlight_rules();
+                    }
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_I
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    (*ptr).rule_stats_panel.toggle();
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_T
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    if winsafe::GetAsyncKeyState(VK::SHIFT) {
+                        (*ptr).filter_time_range();
+                    } else {
+                        (*ptr).go_to_time();
+                    }
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_O
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    (*ptr).open_rotation_matching();
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_J
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    (*ptr).jump_to_source();
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_E
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    (*ptr).edit_exclude_patterns();
+                } else if VK::from_raw(w_param as u16) == VK::CHAR_P
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    let ptr = dw_ref_data as *const Self;
+                    if winsafe::GetAsyncKeyState(VK::SHIFT) {
+                        (*ptr).save_pipeline();
+                    } else {
+                        (*ptr).edit_pipeline();
+                    }
                 }
 
                 debug!(
@@ -225,31 +1850,14 @@ impl GorlMainWindow {
                 info!("WM_CREATE");
                 myself.wnd.hwnd().DragAcceptFiles(true);
                 let _ = crate::utils::try_set_dark_mode(myself.wnd.hwnd());
-                if let Ok(settings) = SETTINGS.read() {
-                    let mut font = HFONT::CreateFont(
-                        SIZE::new(0, settings.font.size),
-                        0,
-                        0,
-                        FW::MEDIUM,
-                        settings.font.italic,
-                        false,
-                        false,
-                        CHARSET::DEFAULT,
-                        OUT_PRECIS::DEFAULT,
-                        CLIP::DEFAULT_PRECIS,
-                        QUALITY::CLEARTYPE,
-                        PITCH::FIXED,
-                        settings.font.name.as_str(),
-                    )?;
-
-                    myself.list_view.hwnd().SendMessage(
-                        SetFont {
-                            hfont: font.leak(),
-                            redraw: true,
-                        }
-                        .as_generic_wm(),
-                    );
-                }
+                let mut font = Self::create_font(TextStyle::default())?;
+                myself.list_view.hwnd().SendMessage(
+                    SetFont {
+                        hfont: font.leak(),
+                        redraw: true,
+                    }
+                    .as_generic_wm(),
+                );
 
                 unsafe {
                     match myself.list_view.hwnd().SetWindowSubclass(
@@ -270,34 +1878,39 @@ impl GorlMainWindow {
             }
         });
 
+        self.wnd.on().wm_destroy({
+            let myself = self.clone();
+            move || {
+                OPEN_FILES
+                    .write()
+                    .unwrap()
+                    .retain(|f| f.window_id != myself.window_id);
+                myself.search_window.save_history();
+
+                // closes a merged or rotated log, so its decompressed members can be removed
+                *myself.view.write().unwrap() = None;
+                *myself.merged.write().unwrap() = None;
+                *myself.rotated.write().unwrap() = None;
+                Ok(())
+            }
+        });
+
         self.wnd.on().wm_drop_files({
             let myself = self.clone();
             move |mut msg| {
                 if let Ok(itr) = msg.hdrop.DragQueryFile() {
-                    for f in itr {
-                        info!("Dropped FILE={:?}", f);
-                        if let Ok(f) = f {
-                            match myself.open_file(&f) {
-                                Ok(view) => {
-                                    {
-                                        *myself.view.write().unwrap() = Some(view);
-                                    }
-                                    myself.list_view.items().set_count(
-                                        myself.view.read().unwrap().as_ref().unwrap().line_count()
-                                            as u32,
-                                        None,
-                                    );
-                                    myself.wnd.set_text(format!("GORL - {f}").as_str());
-                                    info!(
-                                        "set {f}. lines = {}",
-                                        myself.view.read().unwrap().as_ref().unwrap().line_count()
-                                    );
-                                    myself.search_window.set_file(&f);
-                                }
-                                Err(e) => {
-                                    error!("could not open {f}. ERR={:?}", e)
-                                }
-                            }
+                    let files: Vec<String> = itr
+                        .filter_map(|f| {
+                            info!("Dropped FILE={:?}", f);
+                            f.ok()
+                        })
+                        .collect();
+
+                    if files.len() > 1 && myself.confirm_merge(files.len()) {
+                        myself.merge(files);
+                    } else {
+                        for f in files {
+                            myself.show_dropped(&f);
                         }
                     }
                 }
@@ -305,6 +1918,20 @@ impl GorlMainWindow {
             }
         });
 
+        self.level_combo.on().cbn_sel_change({
+            let myself = self.clone();
+            move || {
+                let min_level = myself
+                    .level_combo
+                    .items()
+                    .selected_index()
+                    .and_then(|idx| LogLevel::ALL.get((idx as usize).checked_sub(1)?).copied());
+                myself.set_min_level(min_level);
+                myself.list_view.focus();
+                Ok(())
+            }
+        });
+
         self.list_view.on().nm_custom_draw({
             let myself = self.clone();
             move |draw: &mut winsafe::NMLVCUSTOMDRAW| match draw.mcd.dwDrawStage {
@@ -312,38 +1939,65 @@ impl GorlMainWindow {
                     debug!("PREPAINT");
                     Ok(co::CDRF::NOTIFYITEMDRAW)
                 }
-                CDDS::ITEMPREPAINT => {
-                    if let Ok(line) = myself
-                        .view
-                        .write()
-                        .unwrap()
-                        .as_mut()
-                        .unwrap()
-                        .get_line(draw.mcd.dwItemSpec as u64)
-                    {
-                        if let Some(highlight) = &myself.highlighter.matches(line.as_str()) {
-                            let txt_clr = COLORREF::new(
-                                highlight.fg_color.0,
-                                highlight.fg_color.1,
-                                highlight.fg_color.2,
-                            );
-                            draw.clrText = txt_clr;
-
-                            let bg_clr = COLORREF::new(
-                                highlight.bg_color.0,
-                                highlight.bg_color.1,
-                                highlight.bg_color.2,
-                            );
-                            draw.clrTextBk = bg_clr;
-
-                            debug!(
-                                "nm_custom_draw::ITEMPREPAINT::draw.mcd.dwItemSpec={} MATCHED;",
-                                draw.mcd.dwItemSpec
-                            );
+                stage if stage == CDDS::ITEMPREPAINT || stage == CDDS::ITEMPREPAINT | CDDS::SUBITEM => {
+                    let (line, style) = match myself.styled_row(draw.mcd.dwItemSpec as u64) {
+                        Some(styled) => styled,
+                        None => return Ok(co::CDRF::DODEFAULT),
+                    };
+
+                    let mut default = co::CDRF::DODEFAULT;
+                    if let Some(highlight) = &style.line {
+                        let txt_clr = COLORREF::new(
+                            highlight.fg_color.0,
+                            highlight.fg_color.1,
+                            highlight.fg_color.2,
+                        );
+                        draw.clrText = txt_clr;
+
+                        let bg_clr = COLORREF::new(
+                            highlight.bg_color.0,
+                            highlight.bg_color.1,
+                            highlight.bg_color.2,
+                        );
+                        draw.clrTextBk = bg_clr;
+
+                        if !highlight.text_style.is_plain() {
+                            match myself.select_styled_font(&draw.mcd.hdc, highlight.text_style) {
+                                Ok(_) => default = co::CDRF::NEWFONT,
+                                Err(e) => error!("nm_custom_draw: could not select font: {e}"),
+                            }
                         }
+
+                        debug!(
+                            "nm_custom_draw::ITEMPREPAINT::draw.mcd.dwItemSpec={} MATCHED;",
+                            draw.mcd.dwItemSpec
+                        );
+                    }
+
+                    // selected rows keep the default look, so the selection stays visible
+                    let selected = myself
+                        .list_view
+                        .items()
+                        .get(draw.mcd.dwItemSpec as u32)
+                        .is_selected();
+                    if style.spans.is_empty() || selected {
+                        return Ok(default);
                     }
 
-                    Ok(co::CDRF::DODEFAULT)
+                    if stage == CDDS::ITEMPREPAINT {
+                        // spans are drawn per column, see below
+                        Ok(co::CDRF::NOTIFYSUBITEMDRAW | default)
+                    } else if draw.iSubItem == 1 {
+                        match myself.draw_styled_text(&draw.mcd.hdc, draw.mcd.dwItemSpec as u32, &line, &style) {
+                            Ok(_) => Ok(co::CDRF::SKIPDEFAULT),
+                            Err(e) => {
+                                error!("nm_custom_draw: could not draw spans: {e}");
+                                Ok(default)
+                            }
+                        }
+                    } else {
+                        Ok(default)
+                    }
                 }
                 _ => Ok(co::CDRF::DODEFAULT),
             }
@@ -360,15 +2014,26 @@ impl GorlMainWindow {
                 info.item.lParam = 1337;
 
                 if info.item.mask.has(co::LVIF::TEXT) {
-                    let index = info.item.iItem as usize;
+                    let index = match myself.line_of_row(info.item.iItem as u64) {
+                        Some(index) => index,
+                        None => return Ok(()),
+                    };
                     if info.item.iSubItem == 0 {
+                        // rows of a merged view are tagged with the file and line they came from
+                        let number = match myself.merged.read().unwrap().as_ref() {
+                            Some(merged) => match merged.origin(index) {
+                                Some((source, line)) => format!("{}:{}", merged.tag(source), line + 1),
+                                None => format!("{}", index + 1),
+                            },
+                            None => format!("{}", index + 1),
+                        };
                         let (ptr, cch) = info.item.raw_pszText(); // retrieve raw pointer
                         let out_slice = unsafe { std::slice::from_raw_parts_mut(ptr, cch as _) };
-                        WString::from_str(format!("{}", index + 1)).copy_to_slice(out_slice);
+                        WString::from_str(number).copy_to_slice(out_slice);
                     } else {
                         let line_text = if let Ok(mut lock_res) = myself.view.write() {
                             if let Some(view_ref) = lock_res.as_mut() {
-                                Ok(view_ref.get_line(index as u64))
+                                Ok(view_ref.get_line(index))
                             } else {
                                 Err("Could not get lock view ref mutably INNER")
                             }
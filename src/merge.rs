@@ -0,0 +1,222 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::info;
+
+use crate::log_reader::ChainedLog;
+use crate::search::CompressedSearchResults;
+use crate::timestamp::{Millis, TimestampFormat};
+
+/// Several log files interleaved by time and read as one log, see `merge_files`.
+#[derive(Debug)]
+pub(crate) struct MergedLog {
+    /// The name the merged log is opened with, see `open_log`. Unregistered once this is
+    /// dropped.
+    pub path: String,
+    pub sources: Vec<String>,
+    /// For each source, the 1-based lines of the merged file taken from it, like search
+    /// results.
+    pub source_lines: Vec<Arc<CompressedSearchResults>>,
+}
+
+impl MergedLog {
+    /// The source of the 0-based `line` of the merged file, and the 0-based line within it.
+    pub fn origin(&self, line: u64) -> Option<(usize, u64)> {
+        self.source_lines
+            .iter()
+            .enumerate()
+            .find_map(|(source, lines)| {
+                let idx = lines.lower_bound(line + 1)?;
+                (lines.get(idx) == Some(line + 1)).then_some((source, idx as u64))
+            })
+    }
+
+    /// The file name of `source`, shown next to its lines.
+    pub fn tag(&self, source: usize) -> &str {
+        self.sources.get(source).map_or("?", |path| {
+            Path::new(path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or(path)
+        })
+    }
+}
+
+impl Drop for MergedLog {
+    fn drop(&mut self) {
+        ChainedLog::unregister(&self.path);
+    }
+}
+
+/// Where a line is in its file. Only the last line of a file may have no line break.
+struct Line {
+    bytes: Range<u64>,
+    has_line_break: bool,
+}
+
+/// A line with a timestamp together with the lines without one following it, like the lines
+/// of a stack trace, which are kept together when merging.
+struct Entry {
+    time: Millis,
+    lines: Vec<Line>,
+}
+
+/// Reads the entries of one file to merge.
+struct SourceReader {
+    reader: BufReader<File>,
+    format: Option<TimestampFormat>,
+    /// Offset of the next line.
+    offset: u64,
+    buf: Vec<u8>,
+    /// The line with a timestamp that ended the previous entry.
+    pending: Option<(Millis, Line)>,
+}
+
+impl SourceReader {
+    fn open(path: &str, custom: Option<&str>) -> anyhow::Result<Self> {
+        let format = TimestampFormat::detect_in_file(path, custom)?;
+        info!("MERGE: {path} has {format:?} timestamps");
+
+        Ok(Self {
+            reader: BufReader::new(File::open(path)?),
+            format,
+            offset: 0,
+            buf: Vec::new(),
+            pending: None,
+        })
+    }
+
+    /// The next line, whose text is left in `buf`.
+    fn read_line(&mut self) -> anyhow::Result<Option<Line>> {
+        self.buf.clear();
+        let read = self.reader.read_until(b'\n', &mut self.buf)? as u64;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        let line = Line {
+            bytes: self.offset..self.offset + read,
+            has_line_break: self.buf.ends_with(b"\n"),
+        };
+        self.offset += read;
+        Ok(Some(line))
+    }
+
+    /// The timestamp of the line in `buf`.
+    fn timestamp(&self) -> Option<Millis> {
+        self.format.as_ref()?.parse(&String::from_utf8_lossy(&self.buf))
+    }
+
+    /// The next entry. Lines before the first timestamp of the file come first of all.
+    fn next_entry(&mut self) -> anyhow::Result<Option<Entry>> {
+        let mut entry = match self.pending.take() {
+            Some((time, line)) => Entry {
+                time,
+                lines: vec![line],
+            },
+            None => match self.read_line()? {
+                Some(line) => Entry {
+                    time: self.timestamp().unwrap_or(Millis::MIN),
+                    lines: vec![line],
+                },
+                None => return Ok(None),
+            },
+        };
+
+        // without a format every line is an entry of its own, instead of one for the whole file
+        while self.format.is_some() {
+            let line = match self.read_line()? {
+                Some(line) => line,
+                None => break,
+            };
+            match self.timestamp() {
+                Some(time) => {
+                    self.pending = Some((time, line));
+                    break;
+                }
+                None => entry.lines.push(line),
+            }
+        }
+
+        Ok(Some(entry))
+    }
+}
+
+/// Interleaves the lines of the files at `paths` by their timestamps into one log. Nothing is
+/// copied, the merged log reads the lines from the files in their new order.
+///
+/// This is a k-way merge, so the lines of each file keep their order even if some of its
+/// timestamps are out of order. Lines at the same time are taken from the file that comes
+/// first in `paths`. Each file's timestamp format is detected on its own, `custom` is tried
+/// first as in `TimestampIndex::build`.
+pub(crate) fn merge_files(paths: &[String], custom: Option<&str>) -> anyhow::Result<MergedLog> {
+    let start = std::time::Instant::now();
+
+    let mut readers = paths
+        .iter()
+        .map(|path| SourceReader::open(path, custom))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut log = ChainedLog::new(paths.iter().map(PathBuf::from).collect());
+
+    let mut source_lines: Vec<CompressedSearchResults> =
+        paths.iter().map(|_| CompressedSearchResults::new()).collect();
+    let mut buffers: Vec<Vec<u64>> = paths.iter().map(|_| Vec::new()).collect();
+
+    let mut entries: Vec<Option<Entry>> = Vec::with_capacity(paths.len());
+    let mut heap = BinaryHeap::new();
+    for (source, reader) in readers.iter_mut().enumerate() {
+        let entry = reader.next_entry()?;
+        if let Some(entry) = &entry {
+            heap.push(Reverse((entry.time, source)));
+        }
+        entries.push(entry);
+    }
+
+    let mut line_number = 0;
+    while let Some(Reverse((_, source))) = heap.pop() {
+        if let Some(entry) = entries[source].take() {
+            for line in entry.lines {
+                log.push(source, line.bytes);
+                if !line.has_line_break {
+                    log.push_newline();
+                }
+                line_number += 1;
+                source_lines[source].append_line_number(line_number, &mut buffers[source]);
+            }
+        }
+
+        let entry = readers[source].next_entry()?;
+        if let Some(entry) = &entry {
+            heap.push(Reverse((entry.time, source)));
+        }
+        entries[source] = entry;
+    }
+    let path = Arc::new(log).register("merged");
+
+    let source_lines = source_lines
+        .into_iter()
+        .zip(buffers)
+        .map(|(mut lines, mut buffer)| {
+            lines.finish(&mut buffer);
+            Arc::new(lines)
+        })
+        .collect();
+
+    info!(
+        "MERGE: merged {} files into {line_number} lines as {path} in {}ms",
+        paths.len(),
+        start.elapsed().as_millis()
+    );
+
+    Ok(MergedLog {
+        path,
+        sources: paths.to_vec(),
+        source_lines,
+    })
+}
@@ -0,0 +1,245 @@
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::{Arc, RwLock};
+
+use flume::Sender;
+use log::{debug, error};
+use winsafe::co::COLOR;
+use winsafe::gui::{Brush, Horz, Vert};
+use winsafe::{gui, prelude::*, COLORREF, HBRUSH, RECT};
+
+use crate::main_window::MwMessage;
+use crate::search::CompressedSearchResults;
+use crate::timestamp::TimestampIndex;
+
+/// Number of search hits per bucket of consecutive lines, e.g. to draw a minimap.
+///
+/// Buckets are usually of equal size (`per_lines`), but any boundaries may be given, such as
+/// spans of equal time once timestamps are known, see `TimestampIndex::bucket_starts`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct MatchHistogram {
+    /// 0-based first line of every bucket; a bucket ends where the next one starts.
+    pub starts: Vec<u64>,
+    pub counts: Vec<u32>,
+    pub line_count: u64,
+}
+
+impl MatchHistogram {
+    /// Splits the `line_count` lines of the file into (at most) `buckets` buckets of equal size.
+    pub fn per_lines(results: &CompressedSearchResults, line_count: u64, buckets: usize) -> Self {
+        let buckets = (buckets as u64).clamp(1, line_count.max(1));
+        let starts = (0..buckets).map(|i| i * line_count / buckets).collect();
+        Self::with_bucket_starts(results, line_count, starts)
+    }
+
+    /// Counts the hits between the given bucket boundaries, which have to be ascending.
+    pub fn with_bucket_starts(
+        results: &CompressedSearchResults,
+        line_count: u64,
+        starts: Vec<u64>,
+    ) -> Self {
+        // search results are 1-based, so this is the number of hits before the 0-based `line`
+        let hits_before =
+            |line: u64| results.lower_bound(line + 1).unwrap_or(results.get_count()) as u32;
+
+        let counts = starts
+            .iter()
+            .enumerate()
+            .map(|(i, start)| {
+                let end = starts.get(i + 1).copied().unwrap_or(line_count);
+                hits_before(end) - hits_before(*start)
+            })
+            .collect();
+
+        Self {
+            starts,
+            counts,
+            line_count,
+        }
+    }
+
+    pub fn max_count(&self) -> u32 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+
+    pub fn lines_of_bucket(&self, bucket: usize) -> Range<u64> {
+        let start = self.starts.get(bucket).copied().unwrap_or(self.line_count);
+        let end = self.starts.get(bucket + 1).copied().unwrap_or(self.line_count);
+        start..end
+    }
+}
+
+/// Narrow strip beside the main list showing where the search hits are. Clicking it jumps to
+/// the first hit at that height, or to the line there if it has none.
+#[derive(Clone)]
+pub(crate) struct Minimap {
+    wnd: gui::WindowControl,
+    transmitter: Sender<MwMessage>,
+    results: Rc<RwLock<Option<Arc<CompressedSearchResults>>>>,
+    line_count: Rc<RwLock<u64>>,
+    /// Buckets are spans of time instead of lines while these are known.
+    timestamps: Rc<RwLock<Option<Arc<TimestampIndex>>>>,
+}
+
+const BAR_COLOR: (u8, u8, u8) = (255, 140, 0);
+
+impl Minimap {
+    pub fn new(
+        parent: &impl GuiParent,
+        position: (i32, i32),
+        size: (u32, u32),
+        transmitter: Sender<MwMessage>,
+    ) -> Self {
+        let wnd = gui::WindowControl::new(
+            parent,
+            gui::WindowControlOpts {
+                position,
+                size,
+                class_bg_brush: Brush::Color(COLOR::WINDOW),
+                resize_behavior: (Horz::Repos, Vert::Resize),
+                ..Default::default()
+            },
+        );
+
+        let new_self = Self {
+            wnd,
+            transmitter,
+            results: Rc::new(RwLock::new(None)),
+            line_count: Rc::new(RwLock::new(0)),
+            timestamps: Rc::new(RwLock::new(None)),
+        };
+
+        new_self.events();
+        new_self
+    }
+
+    pub fn set_results(&self, results: Option<Arc<CompressedSearchResults>>, line_count: u64) {
+        *self.results.write().unwrap() = results;
+        *self.line_count.write().unwrap() = line_count;
+
+        if let Err(e) = self.wnd.hwnd().InvalidateRect(None, true) {
+            error!("MINIMAP: could not repaint: {e}");
+        }
+    }
+
+    pub fn set_timestamps(&self, timestamps: Option<Arc<TimestampIndex>>) {
+        *self.timestamps.write().unwrap() = timestamps;
+
+        if let Err(e) = self.wnd.hwnd().InvalidateRect(None, true) {
+            error!("MINIMAP: could not repaint: {e}");
+        }
+    }
+
+    /// The histogram currently shown, with one bucket per pixel row.
+    pub fn histogram(&self) -> Option<MatchHistogram> {
+        let height = self.wnd.hwnd().GetClientRect().ok()?.bottom.max(1) as usize;
+
+        self.results
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|results| self.histogram_of(results, height))
+    }
+
+    /// The histogram of `results` with `buckets` buckets, over time if the timestamps are known.
+    fn histogram_of(&self, results: &CompressedSearchResults, buckets: usize) -> MatchHistogram {
+        let line_count = *self.line_count.read().unwrap();
+
+        match self.timestamps.read().unwrap().as_ref() {
+            Some(timestamps) => MatchHistogram::with_bucket_starts(
+                results,
+                line_count,
+                timestamps.bucket_starts(buckets, line_count),
+            ),
+            None => MatchHistogram::per_lines(results, line_count, buckets),
+        }
+    }
+
+    fn paint(&self) -> anyhow::Result<()> {
+        let hdc = self.wnd.hwnd().BeginPaint()?;
+        let rc = self.wnd.hwnd().GetClientRect()?;
+
+        let histogram = match self.histogram() {
+            Some(histogram) if !histogram.counts.is_empty() => histogram,
+            _ => return Ok(()),
+        };
+
+        let (r, g, b) = BAR_COLOR;
+        let brush = HBRUSH::CreateSolidBrush(COLORREF::new(r, g, b))?;
+        let max = histogram.max_count().max(1) as i64;
+        let buckets = histogram.counts.len() as i64;
+        let height = rc.bottom.max(1) as i64;
+
+        for y in 0..height {
+            let count = histogram.counts[(y * buckets / height) as usize] as i64;
+            if count == 0 {
+                continue;
+            }
+
+            // even a single hit should stay visible
+            let width = (rc.right as i64 * count / max).max(2) as i32;
+            hdc.FillRect(
+                RECT {
+                    left: 0,
+                    top: y as i32,
+                    right: width,
+                    bottom: y as i32 + 1,
+                },
+                &brush,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn line_at(&self, y: i32) -> Option<u64> {
+        let height = self.wnd.hwnd().GetClientRect().ok()?.bottom.max(1) as u64;
+        let line_count = *self.line_count.read().unwrap();
+        if line_count == 0 {
+            return None;
+        }
+
+        // the lines of the bucket drawn at this pixel row, as in `paint`
+        let results = self.results.read().unwrap().clone();
+        let no_results = CompressedSearchResults::new();
+        let histogram =
+            self.histogram_of(results.as_deref().unwrap_or(&no_results), height as usize);
+        let bucket = y.max(0) as u64 * histogram.counts.len() as u64 / height;
+        let lines = histogram.lines_of_bucket(bucket as usize);
+        let first = lines.start.min(line_count - 1);
+        let last = lines.end.clamp(first + 1, line_count);
+
+        // prefer the first hit within the lines covered by this pixel row
+        let hit = results.as_ref().and_then(|results| {
+            results
+                .lower_bound(first + 1)
+                .and_then(|idx| results.get(idx))
+                .filter(|line| *line <= last)
+        });
+
+        Some(hit.unwrap_or(first + 1))
+    }
+
+    fn events(&self) {
+        self.wnd.on().wm_paint({
+            let myself = self.clone();
+            move || {
+                if let Err(e) = myself.paint() {
+                    error!("MINIMAP: could not paint: {e}");
+                }
+                Ok(())
+            }
+        });
+
+        self.wnd.on().wm_l_button_down({
+            let myself = self.clone();
+            move |msg| {
+                if let Some(line) = myself.line_at(msg.coords.y) {
+                    debug!("MINIMAP: clicked at y={} => line {line}", msg.coords.y);
+                    myself.transmitter.send(MwMessage::JumpTo(line))?;
+                }
+                Ok(())
+            }
+        });
+    }
+}
@@ -0,0 +1,251 @@
+use std::rc::Rc;
+use std::sync::RwLock;
+
+use log::{error, info};
+use winsafe::co::{BS, ES};
+use winsafe::gui::{Horz, Vert};
+use winsafe::msg::wm::Close;
+use winsafe::{gui, prelude::*};
+
+/// Small modal dialog asking the user for a line of text (or several lines, if `multiline`).
+#[derive(Clone)]
+pub(crate) struct PromptDialog {
+    wnd: gui::WindowModal,
+    input: gui::Edit,
+    ok_button: gui::Button,
+    cancel_button: gui::Button,
+    result: Rc<RwLock<Option<String>>>,
+}
+
+impl PromptDialog {
+    /// Shows the dialog and blocks until it is closed. Returns `None` if the user cancelled.
+    pub fn show(parent: &impl GuiParent, title: &str, text: &str, multiline: bool) -> Option<String> {
+        let dialog = Self::new(parent, title, text, multiline);
+
+        if let Err(e) = dialog.wnd.show_modal() {
+            error!("PromptDialog: could not show dialog: {e}");
+            return None;
+        }
+
+        let result = dialog.result.read().unwrap().clone();
+        result
+    }
+
+    fn new(parent: &impl GuiParent, title: &str, text: &str, multiline: bool) -> Self {
+        let input_height = if multiline { 200 } else { 24 };
+
+        let wnd = gui::WindowModal::new(
+            parent,
+            gui::WindowModalOpts {
+                title: title.to_owned(),
+                size: (420, input_height + 64),
+                ..Default::default()
+            },
+        );
+
+        let edit_style = if multiline {
+            ES::MULTILINE | ES::WANTRETURN | ES::AUTOVSCROLL | ES::AUTOHSCROLL
+        } else {
+            ES::LEFT | ES::AUTOHSCROLL
+        };
+
+        let input = gui::Edit::new(
+            &wnd,
+            gui::EditOpts {
+                // the edit control wants CRLF to break lines
+                text: text.replace('\n', "\r\n"),
+                position: (10, 10),
+                width: 400,
+                height: input_height,
+                edit_style,
+                resize_behavior: (Horz::Resize, Vert::Resize),
+                ..Default::default()
+            },
+        );
+
+        let ok_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "OK".to_owned(),
+                position: (220, input_height as i32 + 25),
+                button_style: BS::DEFPUSHBUTTON | BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::Repos),
+                ..Default::default()
+            },
+        );
+
+        let cancel_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "Cancel".to_owned(),
+                position: (320, input_height as i32 + 25),
+                button_style: BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::Repos),
+                ..Default::default()
+            },
+        );
+
+        let new_self = Self {
+            wnd,
+            input,
+            ok_button,
+            cancel_button,
+            result: Rc::new(RwLock::new(None)),
+        };
+
+        new_self.events();
+        new_self
+    }
+
+    fn events(&self) {
+        self.wnd.on().wm_create({
+            let myself = self.clone();
+            move |_msg| {
+                let _ = crate::utils::try_set_dark_mode(myself.wnd.hwnd());
+                myself.input.focus();
+                Ok(0)
+            }
+        });
+
+        self.ok_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                let text = myself.input.text().replace("\r\n", "\n");
+                info!("PromptDialog: OK => {text}");
+                *myself.result.write().unwrap() = Some(text);
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+
+        self.cancel_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+    }
+}
+
+/// Small modal dialog letting the user pick one of several items.
+#[derive(Clone)]
+pub(crate) struct ChoiceDialog {
+    wnd: gui::WindowModal,
+    choice_combo: gui::ComboBox,
+    ok_button: gui::Button,
+    cancel_button: gui::Button,
+    result: Rc<RwLock<Option<usize>>>,
+}
+
+impl ChoiceDialog {
+    /// Shows the dialog and blocks until it is closed. Returns the index of the chosen item, or
+    /// `None` if the user cancelled.
+    pub fn show(
+        parent: &impl GuiParent,
+        title: &str,
+        items: &[String],
+        selected: usize,
+    ) -> Option<usize> {
+        let dialog = Self::new(parent, title, items, selected);
+
+        if let Err(e) = dialog.wnd.show_modal() {
+            error!("ChoiceDialog: could not show dialog: {e}");
+            return None;
+        }
+
+        let result = *dialog.result.read().unwrap();
+        result
+    }
+
+    fn new(parent: &impl GuiParent, title: &str, items: &[String], selected: usize) -> Self {
+        let wnd = gui::WindowModal::new(
+            parent,
+            gui::WindowModalOpts {
+                title: title.to_owned(),
+                size: (420, 88),
+                ..Default::default()
+            },
+        );
+
+        let choice_combo = gui::ComboBox::new(
+            &wnd,
+            gui::ComboBoxOpts {
+                position: (10, 10),
+                width: 400,
+                items: items.to_vec(),
+                selected_item: Some(selected as u32),
+                ..Default::default()
+            },
+        );
+
+        let ok_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "OK".to_owned(),
+                position: (220, 52),
+                button_style: BS::DEFPUSHBUTTON | BS::PUSHBUTTON,
+                ..Default::default()
+            },
+        );
+
+        let cancel_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "Cancel".to_owned(),
+                position: (320, 52),
+                button_style: BS::PUSHBUTTON,
+                ..Default::default()
+            },
+        );
+
+        let new_self = Self {
+            wnd,
+            choice_combo,
+            ok_button,
+            cancel_button,
+            result: Rc::new(RwLock::new(None)),
+        };
+
+        new_self.events();
+        new_self
+    }
+
+    fn events(&self) {
+        self.wnd.on().wm_create({
+            let myself = self.clone();
+            move |_msg| {
+                let _ = crate::utils::try_set_dark_mode(myself.wnd.hwnd());
+                myself.choice_combo.focus();
+                Ok(0)
+            }
+        });
+
+        self.ok_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                let selected = myself.choice_combo.items().selected_index();
+                info!("ChoiceDialog: OK => {selected:?}");
+                *myself.result.write().unwrap() = selected.map(|idx| idx as usize);
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+
+        self.cancel_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+    }
+}
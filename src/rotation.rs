@@ -0,0 +1,181 @@
+use std::cmp::Reverse;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use flate2::read::MultiGzDecoder;
+use log::{debug, info};
+use tempfile::NamedTempFile;
+
+use crate::log_reader::ChainedLog;
+use crate::search::files_matching;
+
+/// The base name of a rotated log and its rotation number: `app.log` is 0, `app.log.N` and
+/// `app.log.N.gz` are N.
+fn rotation_of(path: &Path) -> (String, u32) {
+    let name = path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let name = name.strip_suffix(".gz").unwrap_or(&name);
+
+    match name.rsplit_once('.') {
+        Some((base, number))
+            if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            (base.to_owned(), number.parse().unwrap_or(u32::MAX))
+        }
+        _ => (name.to_owned(), 0),
+    }
+}
+
+/// Orders `paths` from the oldest to the newest: higher rotation numbers first, and files
+/// with the same number, like date stamped ones, by the time they were last modified.
+fn sort_chronologically(paths: &mut [String]) {
+    paths.sort_by_cached_key(|path| {
+        let modified = std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        (Reverse(rotation_of(Path::new(path)).1), modified)
+    });
+}
+
+/// All files of the rotation set `member` belongs to, e.g. `app.log`, `app.log.1` and
+/// `app.log.2.gz` for any of them, from the oldest to the newest.
+pub(crate) fn rotation_set(member: &str) -> anyhow::Result<Vec<String>> {
+    let member_path = Path::new(member);
+    let base = rotation_of(member_path).0;
+    let dir = match member_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut paths: Vec<String> = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && rotation_of(path).0 == base)
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    sort_chronologically(&mut paths);
+    Ok(paths)
+}
+
+/// The files matching the glob `pattern`, from the oldest to the newest as in `rotation_set`.
+pub(crate) fn rotation_set_matching(pattern: &str) -> anyhow::Result<Vec<String>> {
+    let mut paths = files_matching(pattern)?;
+    sort_chronologically(&mut paths);
+    Ok(paths)
+}
+
+/// The files of a rotation set read one after the other as a single log, see `concat_rotation`.
+#[derive(Debug)]
+pub(crate) struct RotatedLog {
+    /// The name the set is opened with, see `open_log`. Unregistered once this is dropped.
+    pub path: String,
+    /// From the oldest to the newest.
+    pub members: Vec<String>,
+    /// The 0-based line of the set each member starts at.
+    pub first_lines: Vec<u64>,
+    /// The decompressed `.gz` members, removed again once this is dropped.
+    decompressed: Vec<NamedTempFile>,
+}
+
+impl RotatedLog {
+    /// The member the 0-based `line` comes from, and the 0-based line within it.
+    pub fn member_of(&self, line: u64) -> Option<(usize, u64)> {
+        let member = self
+            .first_lines
+            .partition_point(|first| *first <= line)
+            .checked_sub(1)?;
+        Some((member, line - self.first_lines[member]))
+    }
+}
+
+impl Drop for RotatedLog {
+    fn drop(&mut self) {
+        ChainedLog::unregister(&self.path);
+        debug!(
+            "ROTATION: closing {} with {} decompressed members",
+            self.path,
+            self.decompressed.len()
+        );
+    }
+}
+
+/// The length, the number of line breaks and the last byte of the file at `path`.
+fn scan_lines(path: &Path) -> anyhow::Result<(u64, u64, Option<u8>)> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; 64 * 1024];
+    let (mut len, mut line_breaks, mut last_byte) = (0u64, 0u64, None);
+
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        len += read as u64;
+        line_breaks += buf[..read].iter().filter(|b| **b == b'\n').count() as u64;
+        last_byte = Some(buf[read - 1]);
+    }
+
+    Ok((len, line_breaks, last_byte))
+}
+
+/// Chains the `members` of a rotation set, ordered from the oldest to the newest, into one
+/// log, so the set can be viewed and searched like a single file with global line numbers.
+/// The members are read in place; only the ones ending in `.gz` are decompressed into
+/// temporary files.
+pub(crate) fn concat_rotation(members: &[String]) -> anyhow::Result<RotatedLog> {
+    let start = std::time::Instant::now();
+
+    let mut decompressed = Vec::new();
+    let mut sources = Vec::with_capacity(members.len());
+    for member in members {
+        if member.ends_with(".gz") {
+            let mut temp = tempfile::Builder::new()
+                .prefix("gorl-rotated-")
+                .suffix(".log")
+                .tempfile()?;
+            std::io::copy(
+                &mut MultiGzDecoder::new(File::open(member)?),
+                temp.as_file_mut(),
+            )?;
+            sources.push(temp.path().to_path_buf());
+            decompressed.push(temp);
+        } else {
+            sources.push(PathBuf::from(member));
+        }
+    }
+
+    let mut log = ChainedLog::new(sources.clone());
+    let mut first_lines = Vec::with_capacity(members.len());
+    let mut line_count = 0u64;
+    for (source, path) in sources.iter().enumerate() {
+        first_lines.push(line_count);
+
+        let (len, line_breaks, last_byte) = scan_lines(path)?;
+        log.push(source, 0..len);
+        line_count += line_breaks;
+
+        // the next member starts on a line of its own
+        if last_byte.is_some_and(|byte| byte != b'\n') {
+            log.push_newline();
+            line_count += 1;
+        }
+    }
+
+    let path = Arc::new(log).register("rotated");
+    info!(
+        "ROTATION: chained {} files with {line_count} lines as {path} in {}ms",
+        members.len(),
+        start.elapsed().as_millis()
+    );
+
+    Ok(RotatedLog {
+        path,
+        members: members.to_vec(),
+        first_lines,
+        decompressed,
+    })
+}
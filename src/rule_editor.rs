@@ -0,0 +1,608 @@
+use std::rc::Rc;
+use std::sync::RwLock;
+
+use log::{error, info};
+use winsafe::co::{BS, ES, LVIS, LVS, LVS_EX, MB};
+use winsafe::gui::{CheckState, Horz, Vert};
+use winsafe::msg::wm::Close;
+use winsafe::{gui, prelude::*};
+
+use crate::highlighter::{HighlightProfile, HighlightSetting, HighlightTarget, Highlighter, TextStyle};
+use crate::prompt::PromptDialog;
+use crate::SETTINGS;
+
+const TARGETS: [&str; 3] = ["Line", "Match", "Groups"];
+
+/// Parses `#rrggbb` or `r,g,b`.
+fn parse_color(text: &str) -> anyhow::Result<(u8, u8, u8)> {
+    let text = text.trim();
+
+    if let Some(hex) = text.strip_prefix('#') {
+        if hex.len() == 6 && hex.is_ascii() {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+            return Ok((channel(0)?, channel(2)?, channel(4)?));
+        }
+    } else {
+        let channels = text
+            .split(',')
+            .map(|c| c.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>()?;
+        if let [r, g, b] = channels[..] {
+            return Ok((r, g, b));
+        }
+    }
+
+    anyhow::bail!("'{text}' is no colour, expected #rrggbb or r,g,b")
+}
+
+fn format_color((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn describe_target(target: &HighlightTarget) -> String {
+    match target {
+        HighlightTarget::Line => "Line".to_owned(),
+        HighlightTarget::Match => "Match".to_owned(),
+        HighlightTarget::Groups { names } => format!("Groups: {}", names.join(",")),
+    }
+}
+
+fn describe_style(style: &TextStyle) -> String {
+    [(style.bold, 'B'), (style.italic, 'I'), (style.underline, 'U')]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+fn check_state(checked: bool) -> CheckState {
+    if checked {
+        CheckState::Checked
+    } else {
+        CheckState::Unchecked
+    }
+}
+
+/// Modal dialog to add, edit, reorder and disable the highlight rules of one window.
+///
+/// Every change is passed to `on_change` right away, so the window repaints while editing.
+#[derive(Clone)]
+pub(crate) struct RuleEditorDialog {
+    wnd: gui::WindowModal,
+    rules_list: gui::ListView,
+    expr_txt_box: gui::Edit,
+    fg_txt_box: gui::Edit,
+    bg_txt_box: gui::Edit,
+    priority_txt_box: gui::Edit,
+    target_combo: gui::ComboBox,
+    groups_txt_box: gui::Edit,
+    case_check_box: gui::CheckBox,
+    bold_check_box: gui::CheckBox,
+    italic_check_box: gui::CheckBox,
+    underline_check_box: gui::CheckBox,
+    continue_check_box: gui::CheckBox,
+    enabled_check_box: gui::CheckBox,
+    add_button: gui::Button,
+    update_button: gui::Button,
+    remove_button: gui::Button,
+    up_button: gui::Button,
+    down_button: gui::Button,
+    profile_combo: gui::ComboBox,
+    load_button: gui::Button,
+    save_button: gui::Button,
+    ok_button: gui::Button,
+    cancel_button: gui::Button,
+    rules: Rc<RwLock<Vec<HighlightSetting>>>,
+    on_change: Rc<dyn Fn(&[HighlightSetting])>,
+    result: Rc<RwLock<Option<Vec<HighlightSetting>>>>,
+}
+
+impl RuleEditorDialog {
+    /// Shows the dialog and blocks until it is closed. Returns `None` if the user cancelled,
+    /// in which case the caller should go back to the rules it passed in.
+    pub fn show(
+        parent: &impl GuiParent,
+        rules: Vec<HighlightSetting>,
+        on_change: impl Fn(&[HighlightSetting]) + 'static,
+    ) -> Option<Vec<HighlightSetting>> {
+        let dialog = Self::new(parent, rules, Rc::new(on_change));
+
+        if let Err(e) = dialog.wnd.show_modal() {
+            error!("RuleEditorDialog: could not show dialog: {e}");
+            return None;
+        }
+
+        let result = dialog.result.read().unwrap().clone();
+        result
+    }
+
+    fn new(
+        parent: &impl GuiParent,
+        rules: Vec<HighlightSetting>,
+        on_change: Rc<dyn Fn(&[HighlightSetting])>,
+    ) -> Self {
+        let wnd = gui::WindowModal::new(
+            parent,
+            gui::WindowModalOpts {
+                title: "GORL - Highlight rules".to_owned(),
+                size: (640, 440),
+                ..Default::default()
+            },
+        );
+
+        let rules_list = gui::ListView::new(
+            &wnd,
+            gui::ListViewOpts {
+                position: (10, 10),
+                size: (620, 200),
+                columns: vec![
+                    ("On".to_owned(), 40),
+                    ("Prio".to_owned(), 45),
+                    ("Target".to_owned(), 110),
+                    ("Expression".to_owned(), 250),
+                    ("Colours".to_owned(), 110),
+                    ("Style".to_owned(), 45),
+                ],
+                list_view_ex_style: LVS_EX::FULLROWSELECT,
+                list_view_style: LVS::REPORT | LVS::SINGLESEL | LVS::SHOWSELALWAYS,
+                ..Default::default()
+            },
+        );
+
+        let label = |text: &str, position: (i32, i32), width: u32| {
+            gui::Label::new(
+                &wnd,
+                gui::LabelOpts {
+                    text: text.to_owned(),
+                    position,
+                    size: (width, 20),
+                    ..Default::default()
+                },
+            )
+        };
+        let edit = |position: (i32, i32), width: u32| {
+            gui::Edit::new(
+                &wnd,
+                gui::EditOpts {
+                    position,
+                    width,
+                    height: 24,
+                    edit_style: ES::LEFT | ES::AUTOHSCROLL,
+                    ..Default::default()
+                },
+            )
+        };
+        let check_box = |text: &str, position: (i32, i32), width: u32| {
+            gui::CheckBox::new(
+                &wnd,
+                gui::CheckBoxOpts {
+                    text: text.to_owned(),
+                    position,
+                    size: (width, 20),
+                    ..Default::default()
+                },
+            )
+        };
+        let button = |text: &str, position: (i32, i32), button_style: BS| {
+            gui::Button::new(
+                &wnd,
+                gui::ButtonOpts {
+                    height: 24,
+                    width: 90,
+                    text: text.to_owned(),
+                    position,
+                    button_style,
+                    resize_behavior: (Horz::None, Vert::None),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let _expr_label = label("Expression:", (10, 224), 70);
+        let expr_txt_box = edit((85, 220), 545);
+
+        let _fg_label = label("Text:", (10, 254), 70);
+        let fg_txt_box = edit((85, 250), 75);
+        let _bg_label = label("Back:", (170, 254), 40);
+        let bg_txt_box = edit((210, 250), 75);
+        let _priority_label = label("Priority:", (295, 254), 50);
+        let priority_txt_box = edit((350, 250), 40);
+
+        let target_combo = gui::ComboBox::new(
+            &wnd,
+            gui::ComboBoxOpts {
+                position: (400, 250),
+                width: 80,
+                items: TARGETS.iter().map(|t| t.to_string()).collect(),
+                selected_item: Some(0),
+                ..Default::default()
+            },
+        );
+        // the capture group names of a `Groups` rule, comma-separated
+        let groups_txt_box = edit((490, 250), 140);
+
+        let case_check_box = check_box("Ignore case", (10, 284), 95);
+        let bold_check_box = check_box("Bold", (110, 284), 55);
+        let italic_check_box = check_box("Italic", (170, 284), 60);
+        let underline_check_box = check_box("Underline", (235, 284), 85);
+        let continue_check_box = check_box("Continue evaluating", (325, 284), 150);
+        let enabled_check_box = check_box("Enabled", (480, 284), 80);
+
+        let add_button = button("Add", (10, 314), BS::PUSHBUTTON);
+        let update_button = button("Update", (105, 314), BS::PUSHBUTTON);
+        let remove_button = button("Remove", (200, 314), BS::PUSHBUTTON);
+        let up_button = button("Up", (445, 314), BS::PUSHBUTTON);
+        let down_button = button("Down", (540, 314), BS::PUSHBUTTON);
+
+        let _profile_label = label("Profile:", (10, 358), 70);
+        let profile_combo = gui::ComboBox::new(
+            &wnd,
+            gui::ComboBoxOpts {
+                position: (85, 354),
+                width: 255,
+                ..Default::default()
+            },
+        );
+        let load_button = button("Load", (350, 354), BS::PUSHBUTTON);
+        let save_button = button("Save as...", (445, 354), BS::PUSHBUTTON);
+
+        let ok_button = button("OK", (445, 406), BS::DEFPUSHBUTTON | BS::PUSHBUTTON);
+        let cancel_button = button("Cancel", (540, 406), BS::PUSHBUTTON);
+
+        let new_self = Self {
+            wnd,
+            rules_list,
+            expr_txt_box,
+            fg_txt_box,
+            bg_txt_box,
+            priority_txt_box,
+            target_combo,
+            groups_txt_box,
+            case_check_box,
+            bold_check_box,
+            italic_check_box,
+            underline_check_box,
+            continue_check_box,
+            enabled_check_box,
+            add_button,
+            update_button,
+            remove_button,
+            up_button,
+            down_button,
+            profile_combo,
+            load_button,
+            save_button,
+            ok_button,
+            cancel_button,
+            rules: Rc::new(RwLock::new(rules)),
+            on_change,
+            result: Rc::new(RwLock::new(None)),
+        };
+
+        new_self.events();
+        new_self
+    }
+
+    fn profiles() -> Vec<HighlightProfile> {
+        SETTINGS
+            .read()
+            .unwrap()
+            .highlight_profiles
+            .clone()
+            .unwrap_or_default()
+    }
+
+    fn refresh_profile_combo(&self) {
+        let items = self.profile_combo.items();
+        items.delete_all();
+        let names: Vec<String> = Self::profiles().into_iter().map(|p| p.name).collect();
+        if !names.is_empty() {
+            items.add(&names);
+            items.select(Some(0));
+        }
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.rules_list
+            .items()
+            .iter_selected()
+            .next()
+            .map(|item| item.index() as usize)
+    }
+
+    /// Refills the list from the rules, selects `selected` and passes the rules to `on_change`.
+    fn rules_changed(&self, selected: Option<usize>) {
+        let rules = self.rules.read().unwrap().clone();
+
+        let items = self.rules_list.items();
+        items.delete_all();
+        for rule in &rules {
+            items.add(
+                &[
+                    if rule.enabled { "x" } else { "" }.to_owned(),
+                    rule.priority.to_string(),
+                    describe_target(&rule.target),
+                    rule.expr.clone(),
+                    format!("{} on {}", format_color(rule.fg_color), format_color(rule.bg_color)),
+                    describe_style(&rule.text_style),
+                ],
+                None,
+            );
+        }
+
+        if let Some(idx) = selected.filter(|idx| *idx < rules.len()) {
+            let item = items.get(idx as u32);
+            item.select(true);
+            item.ensure_visible();
+        }
+
+        (self.on_change)(&rules);
+    }
+
+    fn load_form(&self, rule: &HighlightSetting) {
+        self.expr_txt_box.set_text(&rule.expr);
+        self.fg_txt_box.set_text(&format_color(rule.fg_color));
+        self.bg_txt_box.set_text(&format_color(rule.bg_color));
+        self.priority_txt_box.set_text(&rule.priority.to_string());
+
+        let (target, groups) = match &rule.target {
+            HighlightTarget::Line => (0, String::new()),
+            HighlightTarget::Match => (1, String::new()),
+            HighlightTarget::Groups { names } => (2, names.join(",")),
+        };
+        self.target_combo.items().select(Some(target));
+        self.groups_txt_box.set_text(&groups);
+
+        self.case_check_box.set_check_state(check_state(rule.case_insensitive));
+        self.bold_check_box.set_check_state(check_state(rule.text_style.bold));
+        self.italic_check_box.set_check_state(check_state(rule.text_style.italic));
+        self.underline_check_box.set_check_state(check_state(rule.text_style.underline));
+        self.continue_check_box.set_check_state(check_state(rule.continue_evaluating));
+        self.enabled_check_box.set_check_state(check_state(rule.enabled));
+    }
+
+    /// The rule described by the form, if its colours and expression are valid.
+    fn form_rule(&self) -> anyhow::Result<HighlightSetting> {
+        let target = match self.target_combo.items().selected_index() {
+            Some(1) => HighlightTarget::Match,
+            Some(2) => HighlightTarget::Groups {
+                names: self
+                    .groups_txt_box
+                    .text()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_owned)
+                    .collect(),
+            },
+            _ => HighlightTarget::Line,
+        };
+
+        let rule = HighlightSetting {
+            fg_color: parse_color(&self.fg_txt_box.text())?,
+            bg_color: parse_color(&self.bg_txt_box.text())?,
+            expr: self.expr_txt_box.text(),
+            case_insensitive: self.case_check_box.is_checked(),
+            target,
+            priority: self.priority_txt_box.text().trim().parse().unwrap_or(0),
+            text_style: TextStyle {
+                bold: self.bold_check_box.is_checked(),
+                italic: self.italic_check_box.is_checked(),
+                underline: self.underline_check_box.is_checked(),
+            },
+            continue_evaluating: self.continue_check_box.is_checked(),
+            enabled: self.enabled_check_box.is_checked(),
+        };
+
+        Highlighter::build_matcher(&rule)?;
+        Ok(rule)
+    }
+
+    /// Like `form_rule`, but tells the user what is wrong with the form.
+    fn checked_form_rule(&self) -> Option<HighlightSetting> {
+        match self.form_rule() {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                let _ = self.wnd.hwnd().MessageBox(
+                    &format!("Invalid rule: {e}"),
+                    "GORL - Highlight rules",
+                    MB::OK | MB::ICONERROR,
+                );
+                None
+            }
+        }
+    }
+
+    fn move_selected(&self, up: bool) {
+        let idx = match self.selected_index() {
+            Some(idx) => idx,
+            None => return,
+        };
+
+        let target = {
+            let mut rules = self.rules.write().unwrap();
+            let target = if up { idx.checked_sub(1) } else { Some(idx + 1) };
+            match target.filter(|target| *target < rules.len()) {
+                Some(target) => {
+                    rules.swap(idx, target);
+                    target
+                }
+                None => return,
+            }
+        };
+
+        self.rules_changed(Some(target));
+    }
+
+    /// Saves the current rules as a profile, replacing one of the same name.
+    fn save_profile(&self) {
+        let current = self.profile_combo.items().selected_text().unwrap_or_default();
+
+        if let Some(name) = PromptDialog::show(&self.wnd, "GORL - Save rules as profile", &current, false) {
+            let name = name.trim().to_owned();
+            if name.is_empty() {
+                return;
+            }
+
+            if let Ok(mut settings) = SETTINGS.write() {
+                let profiles = settings.highlight_profiles.get_or_insert_with(Vec::new);
+                let rules = self.rules.read().unwrap().clone();
+                // keeps the conditions of an existing profile
+                match profiles.iter_mut().find(|p| p.name == name) {
+                    Some(existing) => existing.rules = rules,
+                    None => profiles.push(HighlightProfile {
+                        name: name.clone(),
+                        rules,
+                        conditions: vec![],
+                    }),
+                }
+
+                if let Err(e) =
+                    settings.save_key("highlight_profiles", &settings.highlight_profiles)
+                {
+                    error!("RuleEditorDialog: could not save profile {name}: {e}");
+                }
+            }
+
+            self.refresh_profile_combo();
+            let idx = Self::profiles().iter().position(|p| p.name == name);
+            self.profile_combo.items().select(idx.map(|idx| idx as u32));
+        }
+    }
+
+    fn events(&self) {
+        self.wnd.on().wm_create({
+            let myself = self.clone();
+            move |_msg| {
+                let _ = crate::utils::try_set_dark_mode(myself.wnd.hwnd());
+                myself.refresh_profile_combo();
+                myself.rules_changed(Some(0));
+                Ok(0)
+            }
+        });
+
+        self.rules_list.on().lvn_item_changed({
+            let myself = self.clone();
+            move |msg| {
+                let selected = msg.uNewState.has(LVIS::SELECTED) && !msg.uOldState.has(LVIS::SELECTED);
+                if selected {
+                    let rule = myself.rules.read().unwrap().get(msg.iItem as usize).cloned();
+                    if let Some(rule) = rule {
+                        myself.load_form(&rule);
+                    }
+                }
+                Ok(())
+            }
+        });
+
+        // double click toggles a rule on and off
+        self.rules_list.on().nm_dbl_clk({
+            let myself = self.clone();
+            move |msg| {
+                let idx = msg.iItem as usize;
+                if let Some(rule) = myself.rules.write().unwrap().get_mut(idx) {
+                    rule.enabled = !rule.enabled;
+                } else {
+                    return Ok(());
+                }
+                myself.rules_changed(Some(idx));
+                Ok(())
+            }
+        });
+
+        self.add_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                if let Some(rule) = myself.checked_form_rule() {
+                    let idx = {
+                        let mut rules = myself.rules.write().unwrap();
+                        rules.push(rule);
+                        rules.len() - 1
+                    };
+                    myself.rules_changed(Some(idx));
+                }
+                Ok(())
+            }
+        });
+
+        self.update_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                if let Some(idx) = myself.selected_index() {
+                    if let Some(rule) = myself.checked_form_rule() {
+                        myself.rules.write().unwrap()[idx] = rule;
+                        myself.rules_changed(Some(idx));
+                    }
+                }
+                Ok(())
+            }
+        });
+
+        self.remove_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                if let Some(idx) = myself.selected_index() {
+                    myself.rules.write().unwrap().remove(idx);
+                    myself.rules_changed(Some(idx.saturating_sub(1)));
+                }
+                Ok(())
+            }
+        });
+
+        self.up_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.move_selected(true);
+                Ok(())
+            }
+        });
+
+        self.down_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.move_selected(false);
+                Ok(())
+            }
+        });
+
+        self.load_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                let selected = myself.profile_combo.items().selected_index();
+                if let Some(profile) = selected.and_then(|idx| Self::profiles().get(idx as usize).cloned()) {
+                    info!("RuleEditorDialog: loading profile {}", profile.name);
+                    *myself.rules.write().unwrap() = profile.rules;
+                    myself.rules_changed(Some(0));
+                }
+                Ok(())
+            }
+        });
+
+        self.save_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.save_profile();
+                Ok(())
+            }
+        });
+
+        self.ok_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                let rules = myself.rules.read().unwrap().clone();
+                info!("RuleEditorDialog: OK => {} rules", rules.len());
+                *myself.result.write().unwrap() = Some(rules);
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+
+        self.cancel_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.wnd.hwnd().SendMessage(Close {});
+                Ok(())
+            }
+        });
+    }
+}
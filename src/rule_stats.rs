@@ -0,0 +1,233 @@
+use std::io::{BufRead, BufReader};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use flume::Sender;
+use log::debug;
+use winsafe::co::{BS, LVS, LVS_EX, SW, WS};
+use winsafe::gui::{Horz, Vert};
+use winsafe::{gui, prelude::*};
+
+use crate::highlighter::{HighlightSetting, Highlighter};
+use crate::log_reader::open_log;
+use crate::main_window::MwMessage;
+use crate::search::CompressedSearchResults;
+
+/// The lines matched by each active highlight rule of a file.
+#[derive(Debug)]
+pub(crate) struct RuleStats {
+    /// In the order the rules are evaluated, with 1-based line numbers like search results.
+    pub rules: Vec<(HighlightSetting, Arc<CompressedSearchResults>)>,
+}
+
+/// Runs all rules of `highlighter` over the file in a single pass. Gives up (returning
+/// `None`) once `generation` no longer equals `expected`, i.e. the rules changed meanwhile.
+pub(crate) fn count_rule_hits(
+    highlighter: &Highlighter,
+    path: &str,
+    generation: &AtomicU64,
+    expected: u64,
+) -> anyhow::Result<Option<RuleStats>> {
+    let rules: Vec<HighlightSetting> = highlighter.active_rules().cloned().collect();
+    let mut hits: Vec<CompressedSearchResults> =
+        rules.iter().map(|_| CompressedSearchResults::new()).collect();
+    let mut buffers: Vec<Vec<u64>> = rules.iter().map(|_| Vec::new()).collect();
+
+    let mut reader = BufReader::new(open_log(path)?);
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        if generation.load(Ordering::Relaxed) != expected {
+            debug!("RULE STATS: rules changed, stopping at line {line_number} of {path}");
+            return Ok(None);
+        }
+
+        let text = String::from_utf8_lossy(&buf);
+        let text = text.trim_end_matches(['\r', '\n']);
+        for (idx, matched) in highlighter.matching_rules(text).into_iter().enumerate() {
+            if matched {
+                hits[idx].append_line_number(line_number, &mut buffers[idx]);
+            }
+        }
+    }
+
+    let rules = rules
+        .into_iter()
+        .zip(hits)
+        .zip(buffers)
+        .map(|((rule, mut lines), mut buffer)| {
+            lines.finish(&mut buffer);
+            (rule, Arc::new(lines))
+        })
+        .collect();
+
+    Ok(Some(RuleStats { rules }))
+}
+
+/// Small window listing how many lines each highlight rule matches. Jumps to the first or
+/// next line of the selected rule.
+#[derive(Clone)]
+pub(crate) struct RuleStatsPanel {
+    wnd: gui::WindowModeless,
+    rules_list: gui::ListView,
+    first_button: gui::Button,
+    next_button: gui::Button,
+    stats: Rc<RwLock<Option<Arc<RuleStats>>>>,
+    transmitter: Sender<MwMessage>,
+}
+
+impl RuleStatsPanel {
+    pub fn new(parent: &impl GuiParent, transmitter: Sender<MwMessage>) -> Self {
+        let wnd = gui::WindowModeless::new(
+            parent,
+            gui::WindowModelessOpts {
+                title: "GORL - Highlight rule hits".to_owned(),
+                // hidden until toggled, see `toggle`
+                style: WS::CAPTION | WS::SYSMENU | WS::CLIPCHILDREN | WS::BORDER | WS::SIZEBOX,
+                size: (360, 300),
+                ..Default::default()
+            },
+        );
+
+        let rules_list = gui::ListView::new(
+            &wnd,
+            gui::ListViewOpts {
+                position: (10, 10),
+                size: (340, 244),
+                columns: vec![("Rule".to_owned(), 230), ("Lines".to_owned(), 90)],
+                resize_behavior: (Horz::Resize, Vert::Resize),
+                list_view_ex_style: LVS_EX::FULLROWSELECT,
+                list_view_style: LVS::REPORT | LVS::SINGLESEL | LVS::SHOWSELALWAYS,
+                ..Default::default()
+            },
+        );
+
+        let first_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "First".to_owned(),
+                position: (160, 266),
+                button_style: BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::Repos),
+                ..Default::default()
+            },
+        );
+
+        let next_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 90,
+                text: "Next".to_owned(),
+                position: (260, 266),
+                button_style: BS::DEFPUSHBUTTON | BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::Repos),
+                ..Default::default()
+            },
+        );
+
+        let new_self = Self {
+            wnd,
+            rules_list,
+            first_button,
+            next_button,
+            stats: Rc::new(RwLock::new(None)),
+            transmitter,
+        };
+
+        new_self.events();
+        new_self
+    }
+
+    pub fn toggle(&self) {
+        let show = if self.wnd.hwnd().IsWindowVisible() {
+            SW::HIDE
+        } else {
+            SW::SHOW
+        };
+        self.wnd.hwnd().ShowWindow(show);
+    }
+
+    pub fn set_stats(&self, stats: Option<Arc<RuleStats>>) {
+        let selected = self
+            .rules_list
+            .items()
+            .iter_selected()
+            .next()
+            .map(|item| item.index());
+
+        let items = self.rules_list.items();
+        items.delete_all();
+        if let Some(stats) = &stats {
+            for (rule, lines) in &stats.rules {
+                items.add(&[rule.expr.clone(), lines.get_count().to_string()], None);
+            }
+        }
+
+        if let Some(idx) = selected.filter(|idx| *idx < items.count()) {
+            items.get(idx).select(true);
+        }
+
+        *self.stats.write().unwrap() = stats;
+    }
+
+    fn jump(&self, first: bool) -> anyhow::Result<()> {
+        let selected = self.rules_list.items().iter_selected().next();
+        let lines = selected.and_then(|item| {
+            let stats = self.stats.read().unwrap();
+            stats
+                .as_ref()
+                .and_then(|stats| stats.rules.get(item.index() as usize))
+                .map(|(_, lines)| lines.clone())
+        });
+
+        if let Some(lines) = lines {
+            self.transmitter.send(MwMessage::JumpToNextHit { lines, first })?;
+        }
+        Ok(())
+    }
+
+    fn events(&self) {
+        self.wnd.on().wm_create({
+            let myself = self.clone();
+            move |_msg| {
+                let _ = crate::utils::try_set_dark_mode(myself.wnd.hwnd());
+                Ok(0)
+            }
+        });
+
+        // closing only hides the panel, it belongs to its main window
+        self.wnd.on().wm_close({
+            let myself = self.clone();
+            move || {
+                myself.wnd.hwnd().ShowWindow(SW::HIDE);
+                Ok(())
+            }
+        });
+
+        self.rules_list.on().nm_dbl_clk({
+            let myself = self.clone();
+            move |_| myself.jump(false)
+        });
+
+        self.first_button.on().bn_clicked({
+            let myself = self.clone();
+            move || myself.jump(true)
+        });
+
+        self.next_button.on().bn_clicked({
+            let myself = self.clone();
+            move || myself.jump(false)
+        });
+    }
+}
@@ -1,35 +1,187 @@
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
 use crate::SETTINGS;
 use flume::Sender;
-use grep::regex::RegexMatcherBuilder;
+use grep::matcher::Matcher;
+use grep::regex::{RegexMatcher, RegexMatcherBuilder};
 use grep::searcher::sinks::UTF8;
 use grep::searcher::{BinaryDetection, SearcherBuilder};
 use log::{debug, error, info};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::ops::Range;
 use std::rc::Rc;
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock};
 use bitpacking::{BitPacker, BitPacker8x};
+use serde_derive::{Deserialize, Serialize};
 
 
 
 use winsafe::co::{
     BS, CHARSET, CLIP, COLOR, ES, FW, LVS, LVS_EX, OUT_PRECIS, PITCH, QUALITY, VK, WS,
 };
-use winsafe::gui::{Brush, Horz, ListViewOpts, Vert};
+use winsafe::gui::{Brush, CheckState, Horz, ListViewOpts, Vert};
 use winsafe::msg::wm::SetFont;
 use winsafe::{co, gui, prelude::*, WString, HFONT, SIZE};
-use crate::lineview::LineBasedFileView;
+use crate::export::{export_search_results, parse_line_range, ExportDialog};
+use crate::fuzzy::FuzzyMatcher;
+use crate::lineview::{LineBasedFileView, LineSlice};
+use crate::log_reader::{is_chained, open_log, LogReader};
+use crate::settings::DEF_MAX_SEARCH_HISTORY;
+use crate::timestamp::{TimeRange, TimestampIndex};
+
+use crate::main_window::{MwMessage, OPEN_FILES};
+use crate::prompt::PromptDialog;
+
+
+/// How the query of a search is matched against the lines.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "kind")]
+pub(crate) enum SearchStrategy {
+    #[default]
+    Regex,
+    /// Lines containing the query with at most `max_distance` typos, see `FuzzyMatcher`.
+    Fuzzy { max_distance: u32 },
+}
+
+impl SearchStrategy {
+    const ALL: [SearchStrategy; 4] = [
+        SearchStrategy::Regex,
+        SearchStrategy::Fuzzy { max_distance: 1 },
+        SearchStrategy::Fuzzy { max_distance: 2 },
+        SearchStrategy::Fuzzy { max_distance: 3 },
+    ];
+
+    fn label(&self) -> String {
+        match self {
+            SearchStrategy::Regex => "Regex".to_owned(),
+            SearchStrategy::Fuzzy { max_distance } => format!("Fuzzy ≤{max_distance}"),
+        }
+    }
+}
+
+/// Options of a search, stored together with the query in the search history.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub(crate) struct SearchOptions {
+    pub case_insensitive: bool,
+    pub strategy: SearchStrategy,
+    /// Restricts the search to these lines; not kept in the history.
+    #[serde(skip)]
+    pub line_range: Option<LineSlice>,
+}
 
-use crate::main_window::MwMessage;
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            case_insensitive: true,
+            strategy: SearchStrategy::Regex,
+            line_range: None,
+        }
+    }
+}
 
+/// A query from the search history, or a saved search if it has a `name`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub(crate) struct SavedSearch {
+    #[serde(default)]
+    pub name: String,
+    pub query: String,
+    #[serde(default)]
+    pub options: SearchOptions,
+}
 
-fn search_in_file(query: &str, path: &str) -> anyhow::Result<CompressedSearchResults> {
+impl SavedSearch {
+    fn label(&self) -> String {
+        let case = if self.options.case_insensitive { "" } else { " [Aa]" };
+        let strategy = match self.options.strategy {
+            SearchStrategy::Regex => String::new(),
+            SearchStrategy::Fuzzy { max_distance } => format!(" [~{max_distance}]"),
+        };
 
-    let start = std::time::Instant::now();
+        if self.name.is_empty() {
+            format!("{}{case}{strategy}", self.query)
+        } else {
+            format!("★ {}: {}{case}{strategy}", self.name, self.query)
+        }
+    }
+}
+
+pub(crate) fn search_in_file(query: &str, path: &str) -> anyhow::Result<CompressedSearchResults> {
+    search_with_options(query, &SearchOptions::default(), path)
+}
+
+pub(crate) fn search_with_options(
+    query: &str,
+    options: &SearchOptions,
+    path: &str,
+) -> anyhow::Result<CompressedSearchResults> {
+    match options.strategy {
+        SearchStrategy::Regex => {
+            let matcher = RegexMatcherBuilder::default()
+                .case_insensitive(options.case_insensitive)
+                .line_terminator(Some(b'\n'))
+                .build(query)?;
+            search_with_matcher(matcher, options, path)
+        }
+        SearchStrategy::Fuzzy { max_distance } => {
+            let matcher = FuzzyMatcher::new(query, max_distance, options.case_insensitive)?;
+            search_with_matcher(matcher, options, path)
+        }
+    }
+}
 
-    let matcher = RegexMatcherBuilder::default()
-        .case_insensitive(true)
-        .line_terminator(Some(b'\n'))
-        .build(query)?;
+/// Finds the hits of a query within single lines, e.g. to emphasize them when exporting.
+pub(crate) enum HitMatcher {
+    Regex(RegexMatcher),
+    Fuzzy(FuzzyMatcher),
+}
+
+impl HitMatcher {
+    pub fn new(query: &str, options: &SearchOptions) -> anyhow::Result<Self> {
+        Ok(match options.strategy {
+            SearchStrategy::Regex => HitMatcher::Regex(
+                RegexMatcherBuilder::default()
+                    .case_insensitive(options.case_insensitive)
+                    .build(query)?,
+            ),
+            SearchStrategy::Fuzzy { max_distance } => HitMatcher::Fuzzy(FuzzyMatcher::new(
+                query,
+                max_distance,
+                options.case_insensitive,
+            )?),
+        })
+    }
+
+    /// Byte ranges of all non-empty hits in `text`. A fuzzy hit covers the whole line.
+    pub fn spans(&self, text: &str) -> Vec<Range<usize>> {
+        let mut spans = vec![];
+        let haystack = text.as_bytes();
+
+        let _ = match self {
+            HitMatcher::Regex(matcher) => matcher.find_iter(haystack, |m| {
+                if !m.is_empty() {
+                    spans.push(m.start()..m.end());
+                }
+                true
+            }),
+            HitMatcher::Fuzzy(matcher) => matcher.find_iter(haystack, |m| {
+                spans.push(m.start()..m.end());
+                true
+            }),
+        };
+
+        spans
+    }
+}
+
+fn search_with_matcher<M: Matcher>(
+    matcher: M,
+    options: &SearchOptions,
+    path: &str,
+) -> anyhow::Result<CompressedSearchResults> {
+
+    let start = std::time::Instant::now();
 
     let mut searcher = SearcherBuilder::new()
         .binary_detection(BinaryDetection::quit(b'\x00'))
@@ -37,37 +189,63 @@ fn search_in_file(query: &str, path: &str) -> anyhow::Result<CompressedSearchRes
         .build();
 
     let mut search_res = CompressedSearchResults::new();
-    let mut buffer = Vec::with_capacity(256);
-
-    searcher.search_path(
-        matcher,
-        path,
-        UTF8(|lnum, _| {
-            search_res.append_line_number(lnum as u32, &mut buffer);
-            Ok(true)
-        }),
-    )?;
-
-    if !buffer.is_empty() {
-        search_res.finish(&mut buffer);
+    let mut buffer = Vec::with_capacity(CompressedSearchResults::BLOCK_LEN);
+
+    let first_line = options.line_range.as_ref().map_or(0, |slice| slice.first_line);
+    let sink = UTF8(|lnum, _| {
+        // line numbers are relative to the start of the searched slice
+        let line_number = lnum + first_line;
+        match &options.line_range {
+            Some(slice) if line_number > slice.lines.end => return Ok(false),
+            Some(slice) if line_number <= slice.lines.start => return Ok(true),
+            _ => {}
+        }
+        search_res.append_line_number(line_number, &mut buffer);
+        Ok(true)
+    });
+
+    match &options.line_range {
+        None if is_chained(path) => searcher.search_reader(matcher, open_log(path)?, sink)?,
+        None => searcher.search_path(matcher, path, sink)?,
+        Some(slice) => {
+            let mut file = open_log(path)?;
+            file.seek(SeekFrom::Start(slice.offsets.start))?;
+            let len = slice.offsets.end - slice.offsets.start;
+            searcher.search_reader(matcher, file.take(len), sink)?
+        }
     }
 
+    search_res.finish(&mut buffer);
+
     let took = start.elapsed();
     let mb = humansize::format_size(search_res.get_size(), humansize::WINDOWS);
     info!("search_in_file:: #Res={} took= {}ms, bytes = {mb}", search_res.get_count(), took.as_millis());
     Ok(search_res)
 }
 
+/// One bit-packed block of search results.
+///
+/// Line numbers are stored as `u32` deltas to the previous entry, starting at `base`.
+/// A block is closed early if the gap between two consecutive line numbers does not fit
+/// into a `u32`, so `len` may be smaller than `BLOCK_LEN` for any block, not just the last one.
+///
+/// `base` and `last` are the smallest and the largest line number of the block, which allows
+/// binary searching the blocks without decompressing them.
 struct SearchResultPage {
     pub compressed_0_offset: usize,
     pub compressed_len: usize,
     pub num_bits: u8,
     pub len: usize,
+    pub base: u64,
+    pub last: u64,
+    pub first_index: usize,
 }
 
-struct CompressedSearchResults {
+pub(crate) struct CompressedSearchResults {
     bytes: Vec<u8>,
     pages: Vec<SearchResultPage>,
+    count: usize,
+    block_cache: Mutex<VecDeque<(usize, Arc<[u64]>)>>,
 }
 
 impl CompressedSearchResults {
@@ -75,34 +253,137 @@ impl CompressedSearchResults {
         Self {
             bytes: vec![0; 8192],
             pages: Vec::new(),
+            count: 0,
+            block_cache: Mutex::new(VecDeque::with_capacity(Self::CACHED_BLOCKS)),
         }
     }
-    const BLOCK_LEN: usize = BitPacker8x::BLOCK_LEN;
+    pub const BLOCK_LEN: usize = BitPacker8x::BLOCK_LEN;
+    const CACHED_BLOCKS: usize = 8;
 
-    pub fn get(&self, index: usize) -> Option<u32> {
-        if index > self.get_count() {
+    pub fn get(&self, index: usize) -> Option<u64> {
+        if index >= self.get_count() {
             return None;
         }
 
-        let page_idx = index / Self::BLOCK_LEN;
+        let page_idx = self.pages.partition_point(|p| p.first_index <= index) - 1;
 
         if let Some(page) = self.pages.get(page_idx) {
-            let mut decompressed = vec![0u32; Self::BLOCK_LEN];
-            let bit_packer = BitPacker8x::new();
-
-            bit_packer.decompress_strictly_sorted(None,
-                                                  &self.bytes[page.compressed_0_offset..(page.compressed_0_offset + page.compressed_len)],
-                                                  &mut decompressed,
-                                                  page.num_bits);
-
-            decompressed.get(index % Self::BLOCK_LEN).copied()
+            self.block(page_idx).get(index - page.first_index).copied()
         } else {
             None
         }
     }
 
+    /// Returns the index of the first result that is `>= line_number`, or `None` if all results are smaller.
+    pub fn lower_bound(&self, line_number: u64) -> Option<usize> {
+        let page_idx = self.pages.partition_point(|p| p.last < line_number);
+        let page = self.pages.get(page_idx)?;
+
+        let in_page = self.block(page_idx).partition_point(|l| *l < line_number);
+        Some(page.first_index + in_page)
+    }
+
+    /// Returns the first result that is `> line_number`.
+    pub fn next_after(&self, line_number: u64) -> Option<u64> {
+        self.lower_bound(line_number + 1).and_then(|idx| self.get(idx))
+    }
+
+    /// Returns the last result that is `< line_number`.
+    pub fn previous_before(&self, line_number: u64) -> Option<u64> {
+        match self.lower_bound(line_number).unwrap_or(self.count) {
+            0 => None,
+            idx => self.get(idx - 1),
+        }
+    }
+
+    /// Returns the `n`-th (0-based) line number that is *not* contained in the results,
+    /// i.e. the inverse of `lower_bound` for the complement of the results.
+    pub fn nth_missing(&self, n: u64) -> u64 {
+        let missing_before = |line: u64, index: usize| line - 1 - index as u64;
+
+        let page_idx = self
+            .pages
+            .partition_point(|p| missing_before(p.base, p.first_index) <= n);
+
+        if page_idx == 0 {
+            return n + 1;
+        }
+
+        let page = &self.pages[page_idx - 1];
+        let block = self.block(page_idx - 1);
+        let mut in_page = 0;
+        while in_page < block.len() && missing_before(block[in_page], page.first_index + in_page) <= n {
+            in_page += 1;
+        }
+
+        n + 1 + (page.first_index + in_page) as u64
+    }
+
+    /// Returns the results within the 0-based `lines`.
+    pub fn within(&self, lines: &Range<u64>) -> Self {
+        let first = self.lower_bound(lines.start + 1).unwrap_or(self.count);
+        (first..self.count)
+            .map_while(|index| self.get(index))
+            .take_while(|line| *line <= lines.end)
+            .collect()
+    }
+
+    /// Returns all results that are not contained in `other`.
+    pub fn difference(&self, other: &CompressedSearchResults) -> Self {
+        let mut others = other.iter().peekable();
+
+        self.iter()
+            .filter(|line| {
+                while others.next_if(|o| o < line).is_some() {}
+                others.peek() != Some(line)
+            })
+            .collect()
+    }
+
+    /// Returns all results that are contained in both `self` and `other`.
+    pub fn intersection(&self, other: &CompressedSearchResults) -> Self {
+        let mut others = other.iter().peekable();
+
+        self.iter()
+            .filter(|line| {
+                while others.next_if(|o| o < line).is_some() {}
+                others.peek() == Some(line)
+            })
+            .collect()
+    }
+
+    /// Returns all results that are contained in `self` or `other`.
+    pub fn union(&self, other: &CompressedSearchResults) -> Self {
+        let mut left = self.iter().peekable();
+        let mut right = other.iter().peekable();
+
+        std::iter::from_fn(|| match (left.peek(), right.peek()) {
+            (Some(l), Some(r)) => match l.cmp(r) {
+                Ordering::Less => left.next(),
+                Ordering::Greater => right.next(),
+                Ordering::Equal => {
+                    right.next();
+                    left.next()
+                }
+            },
+            (Some(_), None) => left.next(),
+            (None, _) => right.next(),
+        })
+        .collect()
+    }
+
+    /// Iterates over all results in ascending order, decompressing every block exactly once.
+    pub fn iter(&self) -> SearchResultsIter<'_> {
+        SearchResultsIter {
+            results: self,
+            page_idx: 0,
+            block: Vec::new(),
+            pos: 0,
+        }
+    }
+
     pub fn get_count(&self) -> usize {
-        self.pages.iter().map(|p| p.len).sum()
+        self.count
     }
 
     pub fn get_size(&self) -> usize {
@@ -110,37 +391,84 @@ impl CompressedSearchResults {
         let size_pages = std::mem::size_of::<Vec<SearchResultPage>>() + self.pages.capacity() * std::mem::size_of::<SearchResultPage>();
         size_bytes + size_pages
     }
-    pub fn append_line_number(&mut self, line_number: u32, buffer: &mut Vec<u32>) {
-        if buffer.len() == Self::BLOCK_LEN {
-            self.compress_and_add_page(buffer, buffer.len());
+
+    /// Appends `line_number` to the results. Line numbers have to be appended in strictly
+    /// ascending order; `buffer` holds the not yet compressed tail and has to be passed to
+    /// [`Self::finish`] once all results have been appended.
+    pub fn append_line_number(&mut self, line_number: u64, buffer: &mut Vec<u64>) {
+        let gap_too_large = buffer
+            .last()
+            .is_some_and(|last| line_number - last > u32::MAX as u64);
+
+        if buffer.len() == Self::BLOCK_LEN || gap_too_large {
+            self.compress_and_add_page(buffer);
             buffer.clear();
         }
 
         buffer.push(line_number);
     }
 
-    pub fn finish(&mut self, buffer: &mut Vec<u32>) {
-        let valid_len = buffer.len();
-
-        if valid_len == Self::BLOCK_LEN {
-            self.compress_and_add_page(buffer, valid_len);
-        } else {
-            while buffer.len() < Self::BLOCK_LEN {
-                buffer.push(0);
-            }
-
-            self.compress_and_add_page(buffer, valid_len);
+    pub fn finish(&mut self, buffer: &mut Vec<u64>) {
+        if !buffer.is_empty() {
+            self.compress_and_add_page(buffer);
         }
 
         buffer.clear();
         self.bytes.truncate(self.pages.last().map(|p| p.compressed_0_offset + p.compressed_len).unwrap_or(0));
+        self.bytes.shrink_to_fit();
+    }
+
+    /// Returns the decompressed block `page_idx`, using a small cache of recently used blocks
+    /// so that consecutive lookups in the same area do not decompress the block over and over.
+    fn block(&self, page_idx: usize) -> Arc<[u64]> {
+        let mut cache = self.block_cache.lock().unwrap();
+
+        if let Some((_, block)) = cache.iter().find(|(idx, _)| *idx == page_idx) {
+            return block.clone();
+        }
+
+        let block: Arc<[u64]> = self.decompress_page(&self.pages[page_idx]).into();
+
+        if cache.len() == Self::CACHED_BLOCKS {
+            cache.pop_back();
+        }
+        cache.push_front((page_idx, block.clone()));
+
+        block
     }
 
-    fn compress_and_add_page(&mut self, data: &mut Vec<u32>, valid_len: usize) {
+    fn decompress_page(&self, page: &SearchResultPage) -> Vec<u64> {
+        let mut deltas = vec![0u32; Self::BLOCK_LEN];
         let bit_packer = BitPacker8x::new();
 
+        bit_packer.decompress(&self.bytes[page.compressed_0_offset..(page.compressed_0_offset + page.compressed_len)],
+                              &mut deltas,
+                              page.num_bits);
+
+        let mut current = page.base;
+        deltas[..page.len]
+            .iter()
+            .map(|delta| {
+                current += *delta as u64;
+                current
+            })
+            .collect()
+    }
+
+    fn compress_and_add_page(&mut self, data: &[u64]) {
+        let bit_packer = BitPacker8x::new();
+        let valid_len = data.len();
+        let base = data[0];
+
+        let mut deltas = vec![0u32; Self::BLOCK_LEN];
+        let mut previous = base;
+        for (delta, line) in deltas.iter_mut().zip(data) {
+            *delta = (line - previous) as u32;
+            previous = *line;
+        }
+
         let last_offset_used = self.pages.last().map(|p| p.compressed_0_offset + p.compressed_len).unwrap_or(0);
-        let num_bits: u8 = bit_packer.num_bits_strictly_sorted(None, data.as_slice());
+        let num_bits: u8 = bit_packer.num_bits(deltas.as_slice());
         let max_space_used = 4 * Self::BLOCK_LEN;
 
         let new_len = if self.bytes.len() - last_offset_used >= max_space_used {
@@ -152,19 +480,279 @@ impl CompressedSearchResults {
         self.bytes.resize(new_len, 0);
 
         let slice = self.bytes[last_offset_used..].as_mut();
-        let written = bit_packer.compress_strictly_sorted(None, data.as_slice(), slice, num_bits);
+        let written = bit_packer.compress(deltas.as_slice(), slice, num_bits);
         let page = SearchResultPage {
             compressed_len: written,
             compressed_0_offset: last_offset_used,
             num_bits,
             len: valid_len,
+            base,
+            last: previous,
+            first_index: self.count,
         };
 
+        self.count += valid_len;
         self.pages.push(page);
     }
 }
 
-type SearchResults = Rc<RwLock<Option<CompressedSearchResults>>>;
+impl FromIterator<u64> for CompressedSearchResults {
+    fn from_iter<T: IntoIterator<Item = u64>>(iter: T) -> Self {
+        let mut results = Self::new();
+        let mut buffer = Vec::with_capacity(Self::BLOCK_LEN);
+
+        for line in iter {
+            results.append_line_number(line, &mut buffer);
+        }
+
+        results.finish(&mut buffer);
+        results
+    }
+}
+
+impl std::fmt::Debug for CompressedSearchResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompressedSearchResults")
+            .field("count", &self.count)
+            .field("pages", &self.pages.len())
+            .finish()
+    }
+}
+
+pub(crate) struct SearchResultsIter<'a> {
+    results: &'a CompressedSearchResults,
+    page_idx: usize,
+    block: Vec<u64>,
+    pos: usize,
+}
+
+impl Iterator for SearchResultsIter<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.block.len() {
+            let page = self.results.pages.get(self.page_idx)?;
+            self.block = self.results.decompress_page(page);
+            self.page_idx += 1;
+            self.pos = 0;
+        }
+
+        let line = self.block[self.pos];
+        self.pos += 1;
+        Some(line)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let consumed = self
+            .results
+            .pages
+            .get(self.page_idx)
+            .map_or(self.results.count, |p| p.first_index)
+            - (self.block.len() - self.pos);
+        let remaining = self.results.count - consumed;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Returns all files matching a glob like `C:\logs\**\*.log`.
+pub(crate) fn files_matching(pattern: &str) -> anyhow::Result<Vec<String>> {
+    let matcher = globset::Glob::new(pattern)?.compile_matcher();
+
+    // walk from the longest prefix of the pattern that contains no glob characters
+    let prefix_end = pattern.find(['*', '?', '[', '{']).unwrap_or(pattern.len());
+    let root = match std::path::Path::new(&pattern[..prefix_end]).parent() {
+        Some(root) if !root.as_os_str().is_empty() => root.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+
+    let mut files = Vec::new();
+    let mut dirs = vec![root];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)?.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if matcher.is_match(&path) {
+                files.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Search results of a single file.
+pub(crate) struct FileSearchResults {
+    pub path: String,
+    pub lines: Arc<CompressedSearchResults>,
+}
+
+/// Results of one query over one or more files. Rows are numbered across all files, in file order.
+#[derive(Default)]
+pub(crate) struct MultiFileSearchResults {
+    files: Vec<FileSearchResults>,
+    first_rows: Vec<usize>,
+    count: usize,
+}
+
+impl MultiFileSearchResults {
+    pub fn push(&mut self, path: String, lines: Arc<CompressedSearchResults>) {
+        self.first_rows.push(self.count);
+        self.count += lines.get_count();
+        self.files.push(FileSearchResults { path, lines });
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns the file and the (1-based) line number of `row`.
+    pub fn get(&self, row: usize) -> Option<(&str, u64)> {
+        if row >= self.count {
+            return None;
+        }
+
+        let file_idx = self.first_rows.partition_point(|first| *first <= row) - 1;
+        let file = &self.files[file_idx];
+        file.lines
+            .get(row - self.first_rows[file_idx])
+            .map(|line| (file.path.as_str(), line))
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(|f| f.path.as_str())
+    }
+
+    pub fn for_file(&self, path: &str) -> Option<Arc<CompressedSearchResults>> {
+        self.files
+            .iter()
+            .find(|f| f.path == path)
+            .map(|f| f.lines.clone())
+    }
+}
+
+/// A search run in the background by `SearchWindow::run_search`, see `SearchWindow::show_search`.
+pub(crate) struct FinishedSearch {
+    search: SavedSearch,
+    remember: bool,
+    /// The current file when the search was started.
+    current: Option<String>,
+    generation: u64,
+    /// Taken when the results are shown.
+    results: Mutex<Option<MultiFileSearchResults>>,
+    /// New views of the files with hits other than the current one.
+    views: Mutex<HashMap<String, LineBasedFileView<LogReader>>>,
+}
+
+impl std::fmt::Debug for FinishedSearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FinishedSearch")
+            .field("query", &self.search.query)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+/// Runs `query` over `files`, several files in parallel. Files other than `current` with hits
+/// get a view, so the text of their hits can be shown, unless they are in `known_views`
+/// already. The `excluded` lines are hidden from the results of `current`.
+fn search_files(
+    query: &str,
+    options: &SearchOptions,
+    files: &[String],
+    current: Option<&str>,
+    excluded: Option<&CompressedSearchResults>,
+    known_views: &HashSet<String>,
+) -> (MultiFileSearchResults, HashMap<String, LineBasedFileView<LogReader>>) {
+    let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let mut results = MultiFileSearchResults::default();
+    let mut views = HashMap::new();
+
+    for chunk in files.chunks(parallelism) {
+        let searched: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|file| {
+                    let needs_view = current != Some(file.as_str()) && !known_views.contains(file);
+                    scope.spawn(move || -> anyhow::Result<_> {
+                        let lines = search_with_options(query, options, file)?;
+                        let view = if needs_view && lines.get_count() > 0 {
+                            Some(LineBasedFileView::new(open_log(file)?)?)
+                        } else {
+                            None
+                        };
+                        Ok((lines, view))
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap_or_else(|_| Err(anyhow::anyhow!("search thread panicked"))))
+                .collect()
+        });
+
+        for (file, searched) in chunk.iter().zip(searched) {
+            match searched {
+                Ok((mut lines, view)) => {
+                    let is_current = current == Some(file.as_str());
+                    if is_current {
+                        // hide excluded lines from the results as well
+                        if let Some(excluded) = excluded {
+                            lines = lines.difference(excluded);
+                        }
+                    }
+
+                    if lines.get_count() > 0 || is_current {
+                        if let Some(view) = view {
+                            views.insert(file.clone(), view);
+                        }
+                        results.push(file.clone(), Arc::new(lines));
+                    }
+                }
+                Err(e) => error!("SEARCH WINDOW: could not search {file}: {e}"),
+            }
+        }
+    }
+
+    (results, views)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum SearchScope {
+    CurrentFile,
+    AllOpenFiles,
+    MatchingFiles,
+    LineRange,
+    TimeRange,
+}
+
+impl SearchScope {
+    const ALL: [SearchScope; 5] = [
+        SearchScope::CurrentFile,
+        SearchScope::AllOpenFiles,
+        SearchScope::MatchingFiles,
+        SearchScope::LineRange,
+        SearchScope::TimeRange,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            SearchScope::CurrentFile => "This file",
+            SearchScope::AllOpenFiles => "All open files",
+            SearchScope::MatchingFiles => "Files matching:",
+            SearchScope::LineRange => "Lines (from-to):",
+            SearchScope::TimeRange => "Time (from .. to ..):",
+        }
+    }
+}
+
+pub(crate) type SearchResults = Rc<RwLock<Option<Arc<CompressedSearchResults>>>>;
 
 #[derive(Clone)]
 pub(crate) struct SearchWindow {
@@ -172,14 +760,37 @@ pub(crate) struct SearchWindow {
     search_query_txt_box: gui::Edit,
     search_results_list: gui::ListView,
     search_button: gui::Button,
+    filter_button: gui::Button,
+    scope_combo: gui::ComboBox,
+    files_txt_box: gui::Edit,
+    case_check_box: gui::CheckBox,
+    strategy_combo: gui::ComboBox,
+    history_combo: gui::ComboBox,
+    save_button: gui::Button,
+    export_button: gui::Button,
+    history_pos: Rc<RwLock<Option<usize>>>,
+    last_search: Rc<RwLock<Option<SavedSearch>>>,
+    /// Set when the search history changed since it was last saved, see `save_history`.
+    history_changed: Rc<RwLock<bool>>,
+    /// Counts the searches started, so only the results of the latest one are shown.
+    search_generation: Rc<RwLock<u64>>,
     current_file: Rc<RwLock<Option<String>>>,
     transmitter: Sender<MwMessage>,
-    current_search_results: SearchResults,
-    view: Rc<RwLock<Option<LineBasedFileView<File>>>>,
+    current_search_results: Rc<RwLock<Option<MultiFileSearchResults>>>,
+    view: Rc<RwLock<Option<LineBasedFileView<LogReader>>>>,
+    other_views: Rc<RwLock<HashMap<String, LineBasedFileView<LogReader>>>>,
+    excluded_lines: SearchResults,
+    timestamps: Rc<RwLock<Option<Arc<TimestampIndex>>>>,
 }
 
 impl SearchWindow {
-    pub fn new(parent: &impl GuiParent, transmitter: Sender<MwMessage>, view: Rc<RwLock<Option<LineBasedFileView<File>>>>) -> Self {
+    pub fn new(
+        parent: &impl GuiParent,
+        transmitter: Sender<MwMessage>,
+        view: Rc<RwLock<Option<LineBasedFileView<LogReader>>>>,
+        excluded_lines: SearchResults,
+        timestamps: Rc<RwLock<Option<Arc<TimestampIndex>>>>,
+    ) -> Self {
         let wnd = gui::WindowModeless::new(
             parent,
             gui::WindowModelessOpts {
@@ -199,7 +810,7 @@ impl SearchWindow {
             &wnd,
             gui::ButtonOpts {
                 height: 24,
-                width: 150,
+                width: 75,
                 text: " 🔍 Search".to_owned(),
                 position: (420, 10),
                 button_style: BS::DEFPUSHBUTTON | BS::PUSHBUTTON,
@@ -208,6 +819,19 @@ impl SearchWindow {
             },
         );
 
+        let filter_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 70,
+                text: "Filter".to_owned(),
+                position: (500, 10),
+                button_style: BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::None),
+                ..Default::default()
+            },
+        );
+
         let search_query_txt_box = gui::Edit::new(
             &wnd,
             gui::EditOpts {
@@ -221,12 +845,98 @@ impl SearchWindow {
             },
         );
 
+        let scope_combo = gui::ComboBox::new(
+            &wnd,
+            gui::ComboBoxOpts {
+                position: (10, 44),
+                width: 180,
+                items: SearchScope::ALL.iter().map(|s| s.label().to_owned()).collect(),
+                selected_item: Some(0),
+                ..Default::default()
+            },
+        );
+
+        let files_txt_box = gui::Edit::new(
+            &wnd,
+            gui::EditOpts {
+                text: "".to_string(),
+                position: (200, 44),
+                width: 370,
+                height: 24,
+                edit_style: ES::LEFT | ES::AUTOHSCROLL,
+                resize_behavior: (Horz::Resize, Vert::None),
+                ..Default::default()
+            },
+        );
+
+        let case_check_box = gui::CheckBox::new(
+            &wnd,
+            gui::CheckBoxOpts {
+                text: "Ignore case".to_owned(),
+                position: (10, 80),
+                size: (100, 20),
+                check_state: CheckState::Checked,
+                ..Default::default()
+            },
+        );
+
+        let strategy_combo = gui::ComboBox::new(
+            &wnd,
+            gui::ComboBoxOpts {
+                position: (115, 78),
+                width: 95,
+                items: SearchStrategy::ALL.iter().map(|s| s.label()).collect(),
+                selected_item: Some(0),
+                ..Default::default()
+            },
+        );
+
+        let history_combo = gui::ComboBox::new(
+            &wnd,
+            gui::ComboBoxOpts {
+                position: (215, 78),
+                width: 190,
+                resize_behavior: (Horz::Resize, Vert::None),
+                ..Default::default()
+            },
+        );
+
+        let save_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 75,
+                text: "Save".to_owned(),
+                position: (410, 78),
+                button_style: BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::None),
+                ..Default::default()
+            },
+        );
+
+        let export_button = gui::Button::new(
+            &wnd,
+            gui::ButtonOpts {
+                height: 24,
+                width: 80,
+                text: "Export...".to_owned(),
+                position: (490, 78),
+                button_style: BS::PUSHBUTTON,
+                resize_behavior: (Horz::Repos, Vert::None),
+                ..Default::default()
+            },
+        );
+
         let search_results = gui::ListView::new(
             &wnd,
             ListViewOpts {
-                position: (10, 44),
-                size: (560, 256),
-                columns: vec![("Line".to_string(), 128), ("Text".to_string(), 3200)],
+                position: (10, 112),
+                size: (560, 188),
+                columns: vec![
+                    ("File".to_string(), 160),
+                    ("Line".to_string(), 128),
+                    ("Text".to_string(), 3200),
+                ],
                 resize_behavior: (Horz::Resize, Vert::Resize),
                 list_view_ex_style: LVS_EX::DOUBLEBUFFER | LVS_EX::FULLROWSELECT,
                 list_view_style: LVS::REPORT | LVS::NOLABELWRAP | LVS::OWNERDATA,
@@ -239,16 +949,525 @@ impl SearchWindow {
             search_query_txt_box,
             search_results_list: search_results,
             search_button,
+            filter_button,
+            scope_combo,
+            files_txt_box,
+            case_check_box,
+            strategy_combo,
+            history_combo,
+            save_button,
+            export_button,
+            history_pos: Rc::new(RwLock::new(None)),
+            last_search: Rc::new(RwLock::new(None)),
+            history_changed: Rc::new(RwLock::new(false)),
+            search_generation: Rc::new(RwLock::new(0)),
             current_file: Rc::new(RwLock::new(None)),
             transmitter,
             current_search_results: Rc::new(RwLock::new(None)),
             view,
+            other_views: Rc::new(RwLock::new(HashMap::new())),
+            excluded_lines,
+            timestamps,
         };
 
         new_self.events(); // attach our events
         new_self
     }
 
+    /// Returns the results of the last search in `path`, if that file was part of it.
+    pub fn results_for(&self, path: &str) -> Option<Arc<CompressedSearchResults>> {
+        self.current_search_results
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|results| results.for_file(path))
+    }
+
+    /// Matches the query of the last successful search, if there was one.
+    pub fn last_hit_matcher(&self) -> Option<HitMatcher> {
+        let last = self.last_search.read().unwrap().clone()?;
+        HitMatcher::new(&last.query, &last.options).ok()
+    }
+
+    fn options(&self) -> SearchOptions {
+        SearchOptions {
+            case_insensitive: self.case_check_box.is_checked(),
+            strategy: self
+                .strategy_combo
+                .items()
+                .selected_index()
+                .and_then(|idx| SearchStrategy::ALL.get(idx as usize).copied())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    /// Limits the next searches to the 1-based lines `first..=last` of the current file,
+    /// e.g. the selection of the main window.
+    pub fn set_line_range(&self, first: u64, last: u64) {
+        let index = SearchScope::ALL.iter().position(|s| *s == SearchScope::LineRange);
+        self.scope_combo.items().select(index.map(|i| i as u32));
+        self.files_txt_box.set_text(format!("{first}-{last}").as_str());
+    }
+
+    /// Limits the next searches to the lines of the current file within `time_range`, or
+    /// searches the whole file again for `None`.
+    pub fn set_time_range(&self, time_range: Option<&TimeRange>) {
+        match time_range {
+            Some(time_range) => {
+                let index = SearchScope::ALL.iter().position(|s| *s == SearchScope::TimeRange);
+                self.scope_combo.items().select(index.map(|i| i as u32));
+                self.files_txt_box.set_text(time_range.to_string().as_str());
+            }
+            None if self.scope() == SearchScope::TimeRange => {
+                self.scope_combo.items().select(Some(0));
+                self.files_txt_box.set_text("");
+            }
+            None => {}
+        }
+    }
+
+    /// Parses the "from-to" range of the `LineRange` scope, or maps the time range of the
+    /// `TimeRange` scope to lines, and looks up their byte offsets.
+    fn line_slice(&self) -> anyhow::Result<Option<LineSlice>> {
+        let text = self.files_txt_box.text();
+        let lines = match self.scope() {
+            SearchScope::LineRange => parse_line_range(&text).ok_or_else(|| {
+                anyhow::anyhow!("expected a line range like 100-200, got '{text}'")
+            })?,
+            SearchScope::TimeRange => {
+                let timestamps = self.timestamps.read().unwrap().clone();
+                let timestamps = timestamps
+                    .ok_or_else(|| anyhow::anyhow!("no timestamps known for this file"))?;
+                let range = timestamps
+                    .range()
+                    .ok_or_else(|| anyhow::anyhow!("no timestamps known for this file"))?;
+                let time_range =
+                    TimeRange::parse(&text, &timestamps.format, range, timestamps.offset)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "expected a time range like 14:30 to 14:45, got '{text}'"
+                            )
+                        })?;
+
+                match self.view.write().unwrap().as_mut() {
+                    Some(view) => timestamps.lines_in(&time_range, view),
+                    None => anyhow::bail!("no file is open"),
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        match self.view.read().unwrap().as_ref() {
+            Some(view) => Ok(view.slice(lines)),
+            None => anyhow::bail!("no file is open"),
+        }
+    }
+
+    fn apply_saved_search(&self, search: &SavedSearch) {
+        self.search_query_txt_box.set_text(search.query.as_str());
+        self.case_check_box.set_check_state(if search.options.case_insensitive {
+            CheckState::Checked
+        } else {
+            CheckState::Unchecked
+        });
+
+        let strategy = SearchStrategy::ALL.iter().position(|s| *s == search.options.strategy);
+        self.strategy_combo.items().select(strategy.map(|i| i as u32));
+    }
+
+    /// Saved searches first, followed by the history, most recent first.
+    fn history_entries() -> Vec<SavedSearch> {
+        let settings = SETTINGS.read().unwrap();
+        let saved = settings.saved_searches.iter().flatten();
+        let history = settings.search_history.iter().flatten();
+        saved.chain(history).cloned().collect()
+    }
+
+    fn refresh_history_combo(&self) {
+        let labels: Vec<String> = Self::history_entries().iter().map(|s| s.label()).collect();
+        self.history_combo.items().delete_all();
+        self.history_combo.items().add(&labels);
+    }
+
+    /// Moves `query` to the top of the search history. The history is only written to the
+    /// settings file by `save_history`, not on every search.
+    fn remember(&self, query: &str, options: &SearchOptions) {
+        if query.is_empty() {
+            return;
+        }
+
+        let entry = SavedSearch {
+            name: String::new(),
+            query: query.to_owned(),
+            options: options.clone(),
+        };
+
+        if let Ok(mut settings) = SETTINGS.write() {
+            let max_len = settings.max_search_history.unwrap_or(DEF_MAX_SEARCH_HISTORY);
+            let history = settings.search_history.get_or_insert_with(Vec::new);
+            history.retain(|s| *s != entry);
+            history.insert(0, entry);
+            history.truncate(max_len);
+        }
+        *self.history_changed.write().unwrap() = true;
+
+        *self.history_pos.write().unwrap() = None;
+        self.refresh_history_combo();
+    }
+
+    /// Writes the search history to the settings file if a search of this window changed it.
+    pub fn save_history(&self) {
+        if !std::mem::take(&mut *self.history_changed.write().unwrap()) {
+            return;
+        }
+
+        if let Ok(settings) = SETTINGS.read() {
+            if let Err(e) = settings.save_key("search_history", &settings.search_history) {
+                error!("SEARCH WINDOW: could not save search history: {e}");
+            }
+        }
+    }
+
+    fn save_current_search(&self) {
+        let query = self.search_query_txt_box.text();
+        if query.is_empty() {
+            return;
+        }
+
+        if let Some(name) = PromptDialog::show(&self.wnd, "GORL - Save search as", "", false) {
+            let name = name.trim().to_owned();
+            if name.is_empty() {
+                return;
+            }
+
+            if let Ok(mut settings) = SETTINGS.write() {
+                let saved = settings.saved_searches.get_or_insert_with(Vec::new);
+                saved.retain(|s| s.name != name);
+                saved.push(SavedSearch {
+                    name,
+                    query,
+                    options: self.options(),
+                });
+
+                if let Err(e) = settings.save_key("saved_searches", &settings.saved_searches) {
+                    error!("SEARCH WINDOW: could not save search: {e}");
+                }
+            }
+
+            self.refresh_history_combo();
+        }
+    }
+
+    /// Runs the saved search with the given index (Ctrl+1 runs the first one).
+    fn run_saved_search(&self, index: usize) {
+        let saved = SETTINGS
+            .read()
+            .unwrap()
+            .saved_searches
+            .as_ref()
+            .and_then(|saved| saved.get(index).cloned());
+
+        if let Some(saved) = saved {
+            self.apply_saved_search(&saved);
+            self.search_button.trigger_click();
+        }
+    }
+
+    /// Walks through the history like a shell does: `older` goes back in time.
+    fn recall_history(&self, older: bool) {
+        let history = SETTINGS.read().unwrap().search_history.clone().unwrap_or_default();
+        let mut pos = self.history_pos.write().unwrap();
+
+        let next = match (*pos, older) {
+            (None, true) => Some(0),
+            (Some(p), true) => Some((p + 1).min(history.len().saturating_sub(1))),
+            (Some(0), false) | (None, false) => None,
+            (Some(p), false) => Some(p - 1),
+        };
+
+        match next.and_then(|p| history.get(p)) {
+            Some(entry) => self.apply_saved_search(entry),
+            None => self.search_query_txt_box.set_text(""),
+        }
+        *pos = next;
+    }
+
+    /// Writes all results of the last search to a file, in a background thread.
+    fn export_results(&self) {
+        let files: Vec<_> = match self.current_search_results.read().unwrap().as_ref() {
+            Some(results) => results
+                .files
+                .iter()
+                .map(|f| (f.path.clone(), f.lines.clone()))
+                .collect(),
+            None => return,
+        };
+
+        let defaults = SETTINGS.read().unwrap().export.clone().unwrap_or_default();
+        let options = match ExportDialog::show(&self.wnd, &defaults) {
+            Some(options) => options,
+            None => return,
+        };
+
+        if let Ok(mut settings) = SETTINGS.write() {
+            settings.export = Some(options.clone());
+            if let Err(e) = settings.save_key("export", &settings.export) {
+                error!("SEARCH WINDOW: could not save export options: {e}");
+            }
+        }
+
+        let target = match crate::utils::ask_save_path(
+            self.wnd.hwnd(),
+            "search_results.txt",
+            crate::utils::TEXT_FILE_TYPES,
+        ) {
+            Ok(Some(target)) => target,
+            Ok(None) => return,
+            Err(e) => {
+                error!("SEARCH WINDOW: could not ask for export file: {e}");
+                return;
+            }
+        };
+
+        let wnd = self.wnd.clone();
+        let title = wnd.text();
+        self.wnd.spawn_new_thread(move || {
+            let mut last_update = std::time::Instant::now();
+            let result = export_search_results(&files, &target, &options, |done, total| {
+                if last_update.elapsed().as_millis() > 200 {
+                    last_update = std::time::Instant::now();
+                    let percent = done * 100 / total.max(1);
+                    let wnd_copy = wnd.clone();
+                    wnd.run_ui_thread(move || {
+                        wnd_copy.set_text(format!("GORL - Search - EXPORTING {percent}%").as_str());
+                        Ok(())
+                    });
+                }
+            });
+
+            let title = match result {
+                Ok(lines) => format!("{title} [EXPORTED {lines} lines]"),
+                Err(e) => {
+                    error!("SEARCH WINDOW: could not export to {target}: {e}");
+                    format!("{title} [EXPORT FAILED]")
+                }
+            };
+            let wnd_copy = wnd.clone();
+            wnd.run_ui_thread(move || {
+                wnd_copy.set_text(title.as_str());
+                Ok(())
+            });
+
+            Ok(())
+        });
+    }
+
+    fn scope(&self) -> SearchScope {
+        self.scope_combo
+            .items()
+            .selected_index()
+            .and_then(|idx| SearchScope::ALL.get(idx as usize).copied())
+            .unwrap_or(SearchScope::CurrentFile)
+    }
+
+    /// The open files searched in the current scope. The files of `MatchingFiles` are looked
+    /// up on the search thread instead, see `run_search`.
+    fn open_files_in_scope(&self) -> Vec<String> {
+        let current = self.current_file.read().unwrap().clone();
+
+        match self.scope() {
+            SearchScope::CurrentFile | SearchScope::LineRange | SearchScope::TimeRange => {
+                current.into_iter().collect()
+            }
+            SearchScope::AllOpenFiles => {
+                let mut files: Vec<String> = current.into_iter().collect();
+                for open in OPEN_FILES.read().unwrap().iter() {
+                    if !files.contains(&open.path) {
+                        files.push(open.path.clone());
+                    }
+                }
+                files
+            }
+            SearchScope::MatchingFiles => Vec::new(),
+        }
+    }
+
+    /// Runs `query` on a background thread, see `search_files`. The results are shown once
+    /// delivered as `MwMessage::SearchDone`, and added to the search history if `remember` is
+    /// set.
+    fn run_search(&self, query: &str, options: &SearchOptions, remember: bool) {
+        let search_options = match self.line_slice() {
+            Ok(line_range) => SearchOptions {
+                line_range,
+                ..options.clone()
+            },
+            Err(e) => {
+                error!("SEARCH WINDOW: ERROR DURING SEARCH: {e}");
+                return;
+            }
+        };
+        let search = SavedSearch {
+            name: String::new(),
+            query: query.to_owned(),
+            options: options.clone(),
+        };
+        let pattern = (self.scope() == SearchScope::MatchingFiles)
+            .then(|| self.files_txt_box.text().trim().to_owned());
+        let open_files = self.open_files_in_scope();
+        let current = self.current_file.read().unwrap().clone();
+        let excluded = self.excluded_lines.read().unwrap().clone();
+        let known_views: HashSet<String> =
+            self.other_views.read().unwrap().keys().cloned().collect();
+        let generation = {
+            let mut generation = self.search_generation.write().unwrap();
+            *generation += 1;
+            *generation
+        };
+
+        self.wnd.set_text("GORL - Search - SEARCHING");
+        let wnd = self.wnd.clone();
+        let transmitter = self.transmitter.clone();
+        self.wnd.spawn_new_thread(move || {
+            let files = match pattern {
+                Some(pattern) => files_matching(&pattern),
+                None => Ok(open_files),
+            };
+            let files = match files {
+                Ok(files) => files,
+                Err(e) => {
+                    error!("SEARCH WINDOW: ERROR DURING SEARCH: {e}");
+                    let wnd_copy = wnd.clone();
+                    wnd.run_ui_thread(move || {
+                        wnd_copy.set_text("GORL - Search - SEARCH FAILED");
+                        Ok(())
+                    });
+                    return Ok(());
+                }
+            };
+
+            let (results, views) = search_files(
+                &search.query,
+                &search_options,
+                &files,
+                current.as_deref(),
+                excluded.as_deref(),
+                &known_views,
+            );
+            transmitter.send(MwMessage::SearchDone(Arc::new(FinishedSearch {
+                search,
+                remember,
+                current,
+                generation,
+                results: Mutex::new(Some(results)),
+                views: Mutex::new(views),
+            })))?;
+            Ok(())
+        });
+    }
+
+    /// Shows the results of a search run by `run_search`, unless another search was started
+    /// or another file was shown since.
+    pub fn show_search(&self, finished: &FinishedSearch) {
+        if *self.search_generation.read().unwrap() != finished.generation
+            || *self.current_file.read().unwrap() != finished.current
+        {
+            debug!("SEARCH WINDOW: dropping outdated results of {:?}", finished.search.query);
+            return;
+        }
+        let search_results = match finished.results.lock().unwrap().take() {
+            Some(search_results) => search_results,
+            None => return,
+        };
+
+        if finished.remember {
+            self.remember(&finished.search.query, &finished.search.options);
+        }
+        *self.last_search.write().unwrap() = Some(finished.search.clone());
+
+        // keeps only the views of files with hits, the ones of earlier searches are reused
+        let mut new_views = finished.views.lock().unwrap();
+        let mut previous_views = std::mem::take(&mut *self.other_views.write().unwrap());
+        for path in search_results.paths() {
+            if let Some(view) = new_views.remove(path).or_else(|| previous_views.remove(path)) {
+                self.other_views.write().unwrap().insert(path.to_owned(), view);
+            }
+        }
+
+        if let Ok(mut guard) = self.current_search_results.write() {
+            let len = search_results.get_count();
+            let file_count = search_results.file_count();
+            *guard = Some(search_results);
+
+            let scope = match self.scope() {
+                SearchScope::CurrentFile => {
+                    self.current_file.read().unwrap().clone().unwrap_or_default()
+                }
+                _ => format!("{file_count} files"),
+            };
+
+            self.wnd
+                .set_text(format!("GORL - Search - #RES={len} [{scope}]").as_str());
+
+            info!("SEARCH WINDOW: SEARCH EXECUTED. #RES={}", len);
+            self.search_results_list.items().delete_all();
+            self.search_results_list.items().set_count(len as u32, None);
+
+            if let Err(e) = self.transmitter.send(MwMessage::SearchFinished) {
+                error!("SEARCH WINDOW: could not notify main window: {e}");
+            }
+        } else {
+            error!("COULD NOT LOCK SearchWindow.current_search_results")
+        }
+    }
+
+    /// Runs the last search again, e.g. after the lines excluded from the results changed.
+    pub fn rerun_last_search(&self) {
+        let last = self.last_search.read().unwrap().clone();
+        if let Some(last) = last {
+            if self.current_search_results.read().unwrap().is_some() {
+                info!("SEARCH WINDOW: running {:?} again", last.query);
+                self.run_search(&last.query, &last.options, false);
+            }
+        }
+    }
+
+    fn line_text(&self, path: &str, line: u64) -> Result<String, String> {
+        if self.current_file.read().unwrap().as_deref() == Some(path) {
+            match self.view.write().unwrap().as_mut() {
+                Some(view) => view.get_line(line - 1),
+                None => Err("GORL ERROR IN SEARCH: no view".to_owned()),
+            }
+        } else {
+            match self.other_views.write().unwrap().get_mut(path) {
+                Some(view) => view.get_line(line - 1),
+                None => Err(format!("GORL ERROR IN SEARCH: {path} is not open")),
+            }
+        }
+    }
+
+    /// Sends the jump to the window showing `path`, which might not be the owner of this search window.
+    fn jump_to(&self, path: &str, line: u64) -> anyhow::Result<()> {
+        if self.current_file.read().unwrap().as_deref() == Some(path) {
+            self.transmitter.send(MwMessage::JumpTo(line))?;
+            return Ok(());
+        }
+
+        let window = OPEN_FILES
+            .read()
+            .unwrap()
+            .iter()
+            .find(|open| open.path == path)
+            .cloned();
+
+        match window {
+            Some(window) => window.transmitter.send(MwMessage::JumpTo(line))?,
+            None => info!("SEARCH WINDOW: {path} is not open in any window"),
+        }
+
+        Ok(())
+    }
+
     pub fn set_file(&self, new_path: &str) {
         *self.current_file.write().unwrap() = Some(new_path.to_owned());
         info!("SEARCHWINDOW: set file to {new_path}");
@@ -262,7 +1481,20 @@ impl SearchWindow {
         _u_id_subclass: usize,
         dw_ref_data: usize,
     ) -> isize {
-        if u_msg == co::WM::KEYUP {
+        if u_msg == co::WM::KEYDOWN {
+            unsafe {
+                let ptr = dw_ref_data as *const Self;
+                let key = VK::from_raw(w_param as u16);
+
+                if key == VK::UP || key == VK::DOWN {
+                    (*ptr).recall_history(key == VK::UP);
+                } else if (VK::CHAR_1.raw()..=VK::CHAR_9.raw()).contains(&key.raw())
+                    && winsafe::GetAsyncKeyState(VK::CONTROL)
+                {
+                    (*ptr).run_saved_search((key.raw() - VK::CHAR_1.raw()) as usize);
+                }
+            }
+        } else if u_msg == co::WM::KEYUP {
             unsafe {
                 if VK::from_raw(w_param as u16) == VK::RETURN {
                     debug!(
@@ -308,8 +1540,10 @@ impl SearchWindow {
                             if is_shift_down {
                                 str_to_cpy.push_str(sel_item.text(0).as_str());
                                 str_to_cpy.push_str(" | ");
+                                str_to_cpy.push_str(sel_item.text(1).as_str());
+                                str_to_cpy.push_str(" | ");
                             }
-                            str_to_cpy.push_str(sel_item.text(1).as_str());
+                            str_to_cpy.push_str(sel_item.text(2).as_str());
                         }
 
                         match crate::utils::copy_text_to_clipboard(&h_wnd, str_to_cpy.as_str()) {
@@ -339,6 +1573,7 @@ impl SearchWindow {
             move |_msg| {
                 info!("SEARCH WINDOW: WM_CREATE");
                 let _ = crate::utils::try_set_dark_mode(myself.wnd.hwnd());
+                myself.refresh_history_combo();
                 if let Ok(settings) = SETTINGS.read() {
                     let mut font = HFONT::CreateFont(
                         SIZE::new(0, settings.font.size),
@@ -412,14 +1647,20 @@ impl SearchWindow {
             let myself = self.clone();
             move |msg| {
                 let index = msg.iItem;
-                let lnum_str = myself.search_results_list.items().get(index as u32).text(0);
+                let hit = myself
+                    .current_search_results
+                    .read()
+                    .unwrap()
+                    .as_ref()
+                    .and_then(|results| results.get(index as usize))
+                    .map(|(path, line)| (path.to_owned(), line));
 
-                if let Ok(num) = lnum_str.as_str().parse::<u64>() {
+                if let Some((path, line)) = hit {
                     debug!(
-                        "SEARCH WINDOW: USER DOUBLE CLICKED ON ITEM {index} => parse to line {num}"
+                        "SEARCH WINDOW: USER DOUBLE CLICKED ON ITEM {index} => {path}:{line}"
                     );
 
-                    myself.transmitter.send(MwMessage::JumpTo(num))?;
+                    myself.jump_to(&path, line)?;
                 }
 
                 Ok(())
@@ -439,28 +1680,22 @@ impl SearchWindow {
 
                 if info.item.mask.has(co::LVIF::TEXT) {
                     let index = info.item.iItem as usize;
-                    let line_set = match myself.current_search_results.write() {
+                    let line_set = match myself.current_search_results.read() {
                         Ok(guard) => {
                             if guard.is_some() {
                                 let results = guard.as_ref().unwrap();
-                                if let Some(line) = results.get(index) {
-                                    let split = format!("{line}");
-
-                                    let text_to_set = if info.item.iSubItem == 0 {
-                                        // first col
-                                        WString::from_str(split)
-                                    } else if let Ok(mut lock_res) = myself.view.write() {
-                                        if let Some(view_ref) = lock_res.as_mut() {
-                                            if let Ok(actual_line) = view_ref.get_line((line - 1) as u64) {
-                                                WString::from_str(actual_line)
-                                            } else {
-                                                WString::from_str("GORL ERROR IN SEARCH: Line not found")
-                                            }
-                                        } else {
-                                            WString::from_str("GORL ERROR IN SEARCH: Could not get lock view ref mutably INNER")
-                                        }
-                                    } else {
-                                        WString::from_str("GORL ERROR IN SEARCH: Could not get lock view ref mutably INNER")
+                                if let Some((path, line)) = results.get(index) {
+                                    let text_to_set = match info.item.iSubItem {
+                                        0 => WString::from_str(
+                                            std::path::Path::new(path)
+                                                .file_name()
+                                                .map_or(path.into(), |n| n.to_string_lossy()),
+                                        ),
+                                        1 => WString::from_str(format!("{line}")),
+                                        _ => match myself.line_text(path, line) {
+                                            Ok(actual_line) => WString::from_str(actual_line),
+                                            Err(err) => WString::from_str(err),
+                                        },
                                     };
 
                                     let (ptr, cch) = info.item.raw_pszText(); // retrieve raw pointer
@@ -497,44 +1732,209 @@ impl SearchWindow {
             let myself = self.clone();
             move || {
                 info!("SEARCH WINDOW: SEARCH CLICKED");
-                if let Ok(lock_res) = myself.current_file.read() {
-                    if let Some(file) = lock_res.as_ref() {
-                        let query = myself.search_query_txt_box.text();
-                        match search_in_file(query.as_str(), file.as_str()) {
-                            Ok(search_results) => {
-                                if let Ok(mut guard) = myself.current_search_results.write() {
-                                    let view = search_results;
-                                    let len = view.get_count();
-                                    *guard = Some(view);
-
-                                    myself.wnd.set_text(
-                                        format!(
-                                            "GORL - Search - #RES={} [{}]",
-                                            len,
-                                            myself.current_file.read().unwrap().as_ref().unwrap()
-                                        )
-                                            .as_str(),
-                                    );
-
-                                    info!("SEARCH WINDOW: SEARCH EXECUTED. #RES={}", len);
-                                    myself.search_results_list.items().delete_all();
-                                    myself
-                                        .search_results_list
-                                        .items()
-                                        .set_count(len as u32, None);
-                                    //}
-                                } else {
-                                    error!("COULD NOT LOCK SearchWindow.current_search_results")
-                                }
-                            }
-                            Err(err) => {
-                                error!("SEARCH WINDOW: ERROR DURING SEARCH: {err}");
-                            }
-                        }
-                    }
+                let query = myself.search_query_txt_box.text();
+                let options = myself.options();
+                myself.run_search(&query, &options, true);
+                Ok(())
+            }
+        });
+
+        self.filter_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                info!("SEARCH WINDOW: FILTER CLICKED");
+                let current_file = myself.current_file.read().unwrap().clone();
+                if let Some(results) = current_file.and_then(|file| myself.results_for(&file)) {
+                    myself.transmitter.send(MwMessage::ShowFiltered(results))?;
+                }
+                Ok(())
+            }
+        });
+
+        self.history_combo.on().cbn_sel_change({
+            let myself = self.clone();
+            move || {
+                let selected = myself.history_combo.items().selected_index();
+                if let Some(entry) = selected.and_then(|idx| Self::history_entries().get(idx as usize).cloned()) {
+                    myself.apply_saved_search(&entry);
+                    myself.search_button.trigger_click();
                 }
                 Ok(())
             }
+        });
+
+        self.save_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.save_current_search();
+                Ok(())
+            }
+        });
+
+        self.export_button.on().bn_clicked({
+            let myself = self.clone();
+            move || {
+                myself.export_results();
+                Ok(())
+            }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ascending line numbers with gaps between 1 and `max_gap`, from a fixed seed.
+    fn lines(count: usize, max_gap: u64, seed: u64) -> Vec<u64> {
+        let mut state = seed;
+        let mut line = 0;
+        (0..count)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                line += 1 + (state >> 33) % max_gap;
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let three_blocks = CompressedSearchResults::BLOCK_LEN * 3;
+        for (count, max_gap) in [(0, 1), (1, 1), (1000, 1), (3000, 7), (three_blocks, 100_000)] {
+            let expected = lines(count, max_gap, 1);
+            let results: CompressedSearchResults = expected.iter().copied().collect();
+
+            assert_eq!(results.get_count(), count);
+            assert_eq!(results.iter().collect::<Vec<_>>(), expected);
+            assert_eq!(results.iter().size_hint(), (count, Some(count)));
+            for (index, line) in expected.iter().enumerate() {
+                assert_eq!(results.get(index), Some(*line));
+            }
+            assert_eq!(results.get(count), None);
+        }
+    }
+
+    #[test]
+    fn lines_above_u32() {
+        let big = u32::MAX as u64;
+        let expected = vec![1, 2, big - 1, big, big + 1, 3 * big, 3 * big + 5, u64::MAX / 2];
+        let results: CompressedSearchResults = expected.iter().copied().collect();
+
+        assert_eq!(results.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(results.lower_bound(big + 1), Some(4));
+        assert_eq!(results.next_after(big + 1), Some(3 * big));
+        assert_eq!(results.previous_before(3 * big), Some(big + 1));
+        assert_eq!(results.nth_missing(big - 4), big + 2);
+    }
+
+    #[test]
+    fn blocks_split_on_large_gaps() {
+        let big = u32::MAX as u64;
+        // the largest gap that fits keeps the block, one more starts a new one
+        let results: CompressedSearchResults = [1, 1 + big, 2 + 2 * big, 3 + 2 * big].into_iter().collect();
+        assert_eq!(results.pages.len(), 2);
+        assert_eq!(results.pages[1].first_index, 2);
+        assert_eq!(results.pages[1].base, 2 + 2 * big);
+        assert_eq!(results.iter().collect::<Vec<_>>(), [1, 1 + big, 2 + 2 * big, 3 + 2 * big]);
+
+        // full blocks are closed as well, also right after a split
+        let mut expected = vec![1, 3 + big];
+        expected.extend((1..=CompressedSearchResults::BLOCK_LEN as u64).map(|n| 3 + big + n));
+        let results: CompressedSearchResults = expected.iter().copied().collect();
+        assert_eq!(results.pages.iter().map(|p| p.len).collect::<Vec<_>>(), [1, CompressedSearchResults::BLOCK_LEN, 1]);
+        assert_eq!(results.iter().collect::<Vec<_>>(), expected);
+        for (index, line) in expected.iter().enumerate() {
+            assert_eq!(results.lower_bound(*line), Some(index));
+        }
+    }
+
+    #[test]
+    fn navigation_matches_brute_force() {
+        for (count, max_gap) in [(0, 1), (5, 3), (2000, 3), (2000, 1)] {
+            let expected = lines(count, max_gap, 7);
+            let results: CompressedSearchResults = expected.iter().copied().collect();
+            let last = expected.last().copied().unwrap_or(0) + 3;
+
+            for line in 0..=last {
+                assert_eq!(
+                    results.lower_bound(line),
+                    expected.iter().position(|l| *l >= line),
+                    "lower_bound({line})"
+                );
+                assert_eq!(
+                    results.next_after(line),
+                    expected.iter().copied().find(|l| *l > line),
+                    "next_after({line})"
+                );
+                assert_eq!(
+                    results.previous_before(line),
+                    expected.iter().copied().rev().find(|l| *l < line),
+                    "previous_before({line})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nth_missing_matches_brute_force() {
+        for (count, max_gap) in [(0, 1), (5, 3), (2000, 3), (2000, 1)] {
+            let expected = lines(count, max_gap, 11);
+            let results: CompressedSearchResults = expected.iter().copied().collect();
+
+            let missing: Vec<u64> = (1..expected.last().copied().unwrap_or(0) + 10)
+                .filter(|line| expected.binary_search(line).is_err())
+                .collect();
+            for (n, line) in missing.iter().enumerate() {
+                assert_eq!(results.nth_missing(n as u64), *line, "nth_missing({n})");
+            }
+        }
+    }
+
+    #[test]
+    fn set_operations() {
+        let a: Vec<u64> = lines(1500, 4, 3);
+        let b: Vec<u64> = lines(1500, 4, 5);
+        let (left, right): (CompressedSearchResults, CompressedSearchResults) =
+            (a.iter().copied().collect(), b.iter().copied().collect());
+
+        let in_b = |line: &u64| b.binary_search(line).is_ok();
+        let mut union: Vec<u64> = a.iter().chain(&b).copied().collect();
+        union.sort_unstable();
+        union.dedup();
+
+        assert_eq!(left.intersection(&right).iter().collect::<Vec<_>>(), a.iter().copied().filter(in_b).collect::<Vec<_>>());
+        assert_eq!(left.difference(&right).iter().collect::<Vec<_>>(), a.iter().copied().filter(|l| !in_b(l)).collect::<Vec<_>>());
+        assert_eq!(left.union(&right).iter().collect::<Vec<_>>(), union);
+        assert_eq!(
+            left.within(&(100..200)).iter().collect::<Vec<_>>(),
+            a.iter().copied().filter(|l| (101..=200).contains(l)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn block_cache() {
+        let count = CompressedSearchResults::BLOCK_LEN * (CompressedSearchResults::CACHED_BLOCKS + 4);
+        let expected = lines(count, 5, 13);
+        let results: CompressedSearchResults = expected.iter().copied().collect();
+
+        // the same block is decompressed once while it stays cached
+        assert!(Arc::ptr_eq(&results.block(0), &results.block(0)));
+
+        // more blocks than fit into the cache, back and forth
+        for page_idx in (0..results.pages.len()).chain((0..results.pages.len()).rev()) {
+            let first = results.pages[page_idx].first_index;
+            assert_eq!(results.get(first), Some(expected[first]));
+            assert!(results.block_cache.lock().unwrap().len() <= CompressedSearchResults::CACHED_BLOCKS);
+        }
+        assert_eq!(results.block_cache.lock().unwrap().front().map(|(idx, _)| *idx), Some(0));
+
+        // an evicted block is decompressed again with the same content
+        let cached: Vec<usize> = results.block_cache.lock().unwrap().iter().map(|(idx, _)| *idx).collect();
+        let evicted = (0..results.pages.len()).find(|idx| !cached.contains(idx)).unwrap();
+        let page = &results.pages[evicted];
+        assert_eq!(results.block(evicted)[..], expected[page.first_index..page.first_index + page.len]);
+    }
+}
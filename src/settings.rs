@@ -1,16 +1,29 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail};
 use config::{Config, File};
 use log::{error, info};
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
-use crate::highlighter::HighlightSetting;
+use crate::export::ExportOptions;
+use crate::filter::NamedFilterPipeline;
+use crate::highlighter::{HighlightProfile, HighlightSetting};
+use crate::search::SavedSearch;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub(crate) struct FontSettings {
     pub size: i32,
     pub name: String,
     pub italic: bool,
 }
 
+/// The exclude patterns last used for `file`, see `Settings::file_exclude_patterns`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct FileExcludePatterns {
+    pub file: String,
+    pub patterns: Vec<String>,
+}
+
 impl Default for FontSettings {
     fn default() -> Self {
         Self {
@@ -21,7 +34,7 @@ impl Default for FontSettings {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[allow(unused)]
 pub(crate) struct Settings {
     pub font: FontSettings,
@@ -30,10 +43,35 @@ pub(crate) struct Settings {
     pub max_nb_of_ui_threads: usize,
     pub max_nb_of_lines_to_copy: u32,
     pub default_highlights: Option<Vec<HighlightSetting>>,
+    pub highlight_profiles: Option<Vec<HighlightProfile>>,
+    /// Colour lines by their detected log level where no highlight rule colours them.
+    pub level_colors: Option<bool>,
+    /// Custom format of the timestamps at the start of lines, e.g. `%d.%m.%Y %H:%M:%S%f`,
+    /// tried before the well-known formats. See `TimestampFormat::Custom`.
+    pub timestamp_format: Option<String>,
     pub keep_search_res_in_mem_until: Option<usize>,
+    /// The exclude patterns a window starts with for files without their own, see
+    /// `file_exclude_patterns`.
+    pub exclude_patterns: Option<Vec<String>>,
+    /// The exclude patterns edited in a window, by the file it showed, so they apply again when
+    /// the file is shown next time.
+    pub file_exclude_patterns: Option<Vec<FileExcludePatterns>>,
+    pub saved_filters: Option<Vec<NamedFilterPipeline>>,
+    pub search_history: Option<Vec<SavedSearch>>,
+    pub saved_searches: Option<Vec<SavedSearch>>,
+    pub max_search_history: Option<usize>,
+    pub export: Option<ExportOptions>,
+    /// The settings file the settings were loaded from, `None` if loading failed and the
+    /// defaults are used. Changes are only saved back to a loaded file, see `save_key`.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
 }
 
 pub(crate) const DEF_CACHE_RANGE: u64 = 500;
+pub(crate) const DEF_MAX_SEARCH_HISTORY: usize = 50;
+const SETTINGS_NAME: &str = "settings";
+const SETTINGS_EXTENSIONS: [&str; 7] = ["ron", "toml", "json", "yaml", "yml", "ini", "json5"];
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
@@ -43,7 +81,18 @@ impl Default for Settings {
             max_nb_of_lines_to_copy: 2500,
             font: FontSettings::default(),
             default_highlights: None,
+            highlight_profiles: None,
+            level_colors: Some(true),
+            timestamp_format: None,
             keep_search_res_in_mem_until: Some(32 * 1024 * 1024),
+            exclude_patterns: None,
+            file_exclude_patterns: None,
+            saved_filters: None,
+            search_history: None,
+            saved_searches: None,
+            max_search_history: Some(DEF_MAX_SEARCH_HISTORY),
+            export: None,
+            source: None,
         }
     }
 }
@@ -52,14 +101,18 @@ impl Settings {
     pub fn new() -> Self {
         let s = Config::builder()
             .add_source(config::Environment::with_prefix("GORL"))
-            .add_source(File::with_name("settings"))
+            .add_source(File::with_name(SETTINGS_NAME))
             .build();
 
         if let Ok(config) = s {
-            match config.try_deserialize() {
+            match config.try_deserialize::<Settings>() {
                 Ok(settings) => {
-                    info!("Loaded config");
-                    return settings;
+                    let source = SETTINGS_EXTENSIONS
+                        .iter()
+                        .map(|ext| PathBuf::from(format!("{SETTINGS_NAME}.{ext}")))
+                        .find(|path| path.is_file());
+                    info!("Loaded config from {source:?}");
+                    return Settings { source, ..settings };
                 }
                 Err(err) => {
                     error!("ERROR deserializing config: {err}")
@@ -71,4 +124,297 @@ impl Settings {
 
         Settings::default()
     }
+
+    /// Writes `value` as the top-level setting `key` back to the file the settings were loaded
+    /// from, so a change made at runtime survives a restart.
+    ///
+    /// Only this key is written. Everything else in the file, like comments and settings
+    /// overridden by `GORL_*` environment variables, stays as it is. Nothing is written when
+    /// the settings could not be loaded, so a broken file is never replaced by the defaults.
+    pub fn save_key<T: serde::Serialize>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        let path = self
+            .source
+            .as_ref()
+            .ok_or_else(|| anyhow!("no settings file was loaded, not saving {key}"))?;
+        if path.extension().and_then(|ext| ext.to_str()) != Some("ron") {
+            bail!(
+                "changes can only be saved to a .ron settings file, not to {}",
+                path.display()
+            );
+        }
+
+        let value = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?
+            .replace('\n', "\n    ");
+        let text = std::fs::read_to_string(path)?;
+        let text = set_ron_field(&text, key, &value)?;
+        std::fs::write(path, text)?;
+        info!("Saved {key} to {}", path.display());
+        Ok(())
+    }
+}
+
+/// The number of `#` of the raw string starting at `i`, `None` if there is none.
+fn raw_string_hashes(bytes: &[u8], i: usize) -> Option<usize> {
+    let is_ident = |c: &u8| c.is_ascii_alphanumeric() || *c == b'_';
+    // `r` must start a token, or follow the `b` of a raw byte string
+    let starts_token = match i.checked_sub(1).map(|prev| bytes[prev]) {
+        Some(b'b') => i < 2 || !is_ident(&bytes[i - 2]),
+        Some(prev) => !is_ident(&prev),
+        None => true,
+    };
+    let hashes = bytes[i + 1..].iter().take_while(|c| **c == b'#').count();
+    (starts_token && bytes.get(i + 1 + hashes) == Some(&b'"')).then_some(hashes)
+}
+
+/// Replaces the value of the top-level field `key` of the struct in the RON `text`, or adds the
+/// field at its end. Everything else is kept as it is.
+fn set_ron_field(text: &str, key: &str, value: &str) -> anyhow::Result<String> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    // the end of the last byte that is neither white space nor part of a comment
+    let mut code_end = 0;
+    // the field whose value is being read, and where the value starts
+    let mut field: Option<(&str, usize)> = None;
+    let mut found = None;
+    let mut close = None;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = text[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            }
+            // attributes like `#![enable(implicit_some)]` before the struct
+            b'#' if depth == 0 => {
+                i = text[i..].find(']').map_or(bytes.len(), |end| i + end + 1);
+                continue;
+            }
+            // strings and chars, which may contain brackets, quotes and escapes
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            // raw strings like `r#"..."#`, which end at a quote followed by as many `#`
+            b'r' => {
+                if let Some(hashes) = raw_string_hashes(bytes, i) {
+                    let start = i + hashes + 2;
+                    let end = format!("\"{}", "#".repeat(hashes));
+                    i = text[start..]
+                        .find(&end)
+                        .map_or(bytes.len(), |pos| start + pos + end.len() - 1);
+                }
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                if depth == 1 {
+                    if let Some((name, start)) = field.take() {
+                        if name == key {
+                            found = Some(start..code_end);
+                        }
+                    }
+                    close = Some((code_end, i));
+                }
+                depth = depth.saturating_sub(1);
+            }
+            b',' if depth == 1 => {
+                if let Some((name, start)) = field.take() {
+                    if name == key {
+                        found = Some(start..code_end);
+                    }
+                }
+            }
+            b':' if depth == 1 && field.is_none() => {
+                let before = text[..i].trim_end();
+                let name_start = before
+                    .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .map_or(0, |pos| pos + 1);
+                field = Some((&before[name_start..], i + 1));
+            }
+            _ => {}
+        }
+
+        if !bytes[i.min(bytes.len() - 1)].is_ascii_whitespace() {
+            code_end = (i + 1).min(bytes.len());
+        }
+        i += 1;
+        if close.is_some() {
+            break;
+        }
+    }
+
+    if let Some(range) = found {
+        return Ok(format!(
+            "{} {value}{}",
+            &text[..range.start],
+            &text[range.end..]
+        ));
+    }
+
+    let (close, paren) = close.ok_or_else(|| anyhow!("the settings are not a RON struct"))?;
+    let comma = match text[..close].trim_end().chars().last() {
+        Some(',') | Some('(') => "",
+        _ => ",",
+    };
+    // after a comment at the end of the last line, which belongs to the field before
+    let insert_at = text[close..paren]
+        .find('\n')
+        .map_or(close, |newline| close + newline);
+    Ok(format!(
+        "{}{comma}{}\n    {key}: {value},{}",
+        &text[..close],
+        &text[close..insert_at],
+        &text[insert_at..]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(text: &str, key: &str, value: &str) -> String {
+        set_ron_field(text, key, value).unwrap()
+    }
+
+    #[test]
+    fn replaces_existing_key() {
+        let text = "(\n    cache_size: 500,\n    max_search_history: Some(50),\n)\n";
+        assert_eq!(
+            set(text, "max_search_history", "Some(10)"),
+            "(\n    cache_size: 500,\n    max_search_history: Some(10),\n)\n"
+        );
+        assert_eq!(
+            set(text, "cache_size", "[\n        1,\n    ]"),
+            "(\n    cache_size: [\n        1,\n    ],\n    max_search_history: Some(50),\n)\n"
+        );
+        // the last field without a trailing comma
+        assert_eq!(set("(a: 1, b: 2)", "b", "3"), "(a: 1, b: 3)");
+    }
+
+    #[test]
+    fn appends_new_key() {
+        assert_eq!(
+            set("(\n    cache_size: 500,\n)\n", "export", "None"),
+            "(\n    cache_size: 500,\n    export: None,\n)\n"
+        );
+        assert_eq!(
+            set("(\n    cache_size: 500\n)\n", "export", "None"),
+            "(\n    cache_size: 500,\n    export: None,\n)\n"
+        );
+        // after the comment of the last field
+        let text = "Settings(\n    cache_size: 500 // the default\n)";
+        assert_eq!(
+            set(text, "export", "None"),
+            "Settings(\n    cache_size: 500, // the default\n    export: None,\n)"
+        );
+    }
+
+    #[test]
+    fn nested_structs() {
+        let text = "(\n    font: (size: 8, name: \"Consolas\"),\n    cache_size: 500,\n)";
+        assert_eq!(
+            set(text, "font", "(size: 9, name: \"Arial\")"),
+            "(\n    font: (size: 9, name: \"Arial\"),\n    cache_size: 500,\n)"
+        );
+        // only top-level fields count
+        assert_eq!(
+            set(text, "size", "1"),
+            "(\n    font: (size: 8, name: \"Consolas\"),\n    cache_size: 500,\n    size: 1,\n)"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let text =
+            "(\n    // cache_size: 1, )\n    /* font: ( */ cache_size: 500 /* ] */, // keep\n)\n";
+        assert_eq!(
+            set(text, "cache_size", "10"),
+            "(\n    // cache_size: 1, )\n    /* font: ( */ cache_size: 10 /* ] */, // keep\n)\n"
+        );
+        assert!(set_ron_field("// (a: 1)", "a", "2").is_err());
+    }
+
+    #[test]
+    fn attributes() {
+        let text = "#![enable(implicit_some)]\n(\n    max_search_history: 50,\n)\n";
+        assert_eq!(
+            set(text, "max_search_history", "10"),
+            "#![enable(implicit_some)]\n(\n    max_search_history: 10,\n)\n"
+        );
+        assert_eq!(
+            set(text, "export", "None"),
+            "#![enable(implicit_some)]\n(\n    max_search_history: 50,\n    export: None,\n)\n"
+        );
+    }
+
+    #[test]
+    fn strings_and_chars() {
+        let text = r#"(
+    timestamp_format: Some("%H:%M ( [ { \" , x: 1"),
+    separator: ')',
+    quote: '\'',
+    cache_size: 500,
+)"#;
+        assert_eq!(
+            set(text, "cache_size", "10"),
+            text.replace("cache_size: 500", "cache_size: 10")
+        );
+        assert_eq!(
+            set(text, "separator", "'('"),
+            text.replace("separator: ')'", "separator: '('")
+        );
+    }
+
+    #[test]
+    fn raw_strings() {
+        let text = r####"(
+    default_highlights: Some([(pattern: r#"\((\d+)"\]"#), (pattern: r"[)"), (pattern: br##"}"#"##)]),
+    for_r: r"(",
+    cache_size: 500,
+)"####;
+        assert_eq!(
+            set(text, "cache_size", "10"),
+            text.replace("cache_size: 500", "cache_size: 10")
+        );
+        assert_eq!(
+            set(text, "for_r", "r\"\""),
+            text.replace("for_r: r\"(\"", "for_r: r\"\"")
+        );
+    }
+
+    #[test]
+    fn save_key_to_loaded_file() {
+        let mut file = tempfile::Builder::new().suffix(".ron").tempfile().unwrap();
+        std::fs::write(file.path(), "// settings\n(\n    cache_size: 500,\n)\n").unwrap();
+        let settings = Settings {
+            source: Some(file.path().to_path_buf()),
+            ..Settings::default()
+        };
+
+        settings.save_key("max_search_history", &Some(10)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(file.path()).unwrap(),
+            "// settings\n(\n    cache_size: 500,\n    max_search_history: Some(10),\n)\n"
+        );
+
+        // nothing is written without a loaded .ron file
+        assert!(Settings::default().save_key("cache_size", &1).is_err());
+        file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        let settings = Settings {
+            source: Some(file.path().to_path_buf()),
+            ..Settings::default()
+        };
+        assert!(settings.save_key("cache_size", &1).is_err());
+        assert_eq!(std::fs::read_to_string(file.path()).unwrap(), "");
+    }
 }
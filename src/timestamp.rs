@@ -0,0 +1,993 @@
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::lineview::LineBasedFileView;
+use crate::log_reader::open_log;
+
+/// Milliseconds since 1970-01-01 00:00 UTC. Timestamps without an offset are taken as UTC, so
+/// times within one file compare correctly whatever its time zone. Times are shown to and
+/// entered by the user in the offset of the file instead, see `TimestampIndex::offset`.
+pub(crate) type Millis = i64;
+
+const MINUTE_MILLIS: Millis = 60_000;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const DAY_MILLIS: Millis = 86_400_000;
+
+/// Number of lines looked at to detect the timestamp format of a file.
+const DETECT_LINES: usize = 100;
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) as i64 + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of a number of days since 1970-01-01, see `days_from_civil`.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Formats `millis` as the wall-clock time `YYYY-MM-DD HH:MM:SS.mmm` at `offset` minutes from
+/// UTC.
+pub(crate) fn format_millis(millis: Millis, offset: i64) -> String {
+    let millis = millis + offset * MINUTE_MILLIS;
+    let (days, in_day) = (millis.div_euclid(DAY_MILLIS), millis.rem_euclid(DAY_MILLIS));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}.{:03}",
+        in_day / 3_600_000,
+        in_day / 60_000 % 60,
+        in_day / 1000 % 60,
+        in_day % 1000
+    )
+}
+
+fn current_year() -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as Millis);
+    civil_from_days(now.div_euclid(DAY_MILLIS)).0
+}
+
+/// Parses a time entered by the user: an ISO-8601 timestamp, a timestamp in the `format` of
+/// the file, or just a time of day like `14:32`, `14:32:10` or `14:32:10.250`. A time of day
+/// is taken on the first day of the file's `range` whose time is in that range, or on the
+/// first day if there is none.
+///
+/// Times without an offset of their own are wall-clock times at `offset` minutes from UTC,
+/// the offset of the file, so `14:32` is the time as written in the file.
+pub(crate) fn parse_user_time(
+    text: &str,
+    format: &TimestampFormat,
+    range: (Millis, Millis),
+    offset: i64,
+) -> Option<Millis> {
+    let text = text.trim();
+    let offset_millis = offset * MINUTE_MILLIS;
+    if let Some((millis, written_offset)) = TimestampFormat::Iso8601
+        .parse_with_offset(text)
+        .or_else(|| format.parse_with_offset(text))
+    {
+        return Some(match written_offset {
+            Some(_) => millis,
+            None => millis - offset_millis,
+        });
+    }
+
+    let mut cursor = Cursor::new(text);
+    let mut dt = DateTime::new(1970);
+    dt.hour = cursor.digits(1, 2)? as u32;
+    cursor.expect(b':')?;
+    dt.minute = cursor.digits(2, 2)? as u32;
+    if cursor.expect(b':').is_some() {
+        dt.second = cursor.digits(2, 2)? as u32;
+        dt.millis = cursor.fraction();
+    }
+    if cursor.peek().is_some() {
+        return None;
+    }
+
+    // the days of the range as seen in the file
+    let time_of_day = dt.to_millis()?;
+    let (first, last) = (range.0 + offset_millis, range.1 + offset_millis);
+    let first_day = first.div_euclid(DAY_MILLIS) * DAY_MILLIS;
+    let in_range = (first_day..=last)
+        .step_by(DAY_MILLIS as usize)
+        .map(|day| day + time_of_day)
+        .find(|millis| (first..=last).contains(millis));
+    Some(in_range.unwrap_or(first_day + time_of_day) - offset_millis)
+}
+
+/// The parts of a timestamp read so far.
+#[derive(Debug, Clone, Copy)]
+struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    millis: u32,
+    /// Offset from UTC in minutes, if the timestamp has one.
+    offset: Option<i64>,
+}
+
+impl DateTime {
+    fn new(year: i64) -> Self {
+        Self {
+            year,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            millis: 0,
+            offset: None,
+        }
+    }
+
+    fn to_millis(self) -> Option<Millis> {
+        let valid = (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            // leap seconds
+            && self.second <= 60;
+        if !valid {
+            return None;
+        }
+
+        let days = days_from_civil(self.year, self.month, self.day);
+        let seconds =
+            days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+                - self.offset.unwrap_or(0) * 60;
+        Some(seconds * 1000 + self.millis as i64)
+    }
+
+    /// `to_millis` together with the offset.
+    fn to_parsed(self) -> Option<(Millis, Option<i64>)> {
+        Some((self.to_millis()?, self.offset))
+    }
+}
+
+/// Reads a timestamp byte by byte; every method returns `None` if the input doesn't fit.
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            bytes: text.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_while(&mut self, pred: impl Fn(u8) -> bool) {
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Between `min` and `max` digits, as many as there are.
+    fn digits(&mut self, min: usize, max: usize) -> Option<u64> {
+        let start = self.pos;
+        let mut value = 0u64;
+        while self.pos - start < max {
+            match self.peek() {
+                Some(c) if c.is_ascii_digit() => {
+                    value = value * 10 + (c - b'0') as u64;
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+        (self.pos - start >= min).then_some(value)
+    }
+
+    /// A day of month, possibly padded with a space as in syslog.
+    fn day(&mut self) -> Option<u32> {
+        if self.peek() == Some(b' ') {
+            self.pos += 1;
+            return self.digits(1, 1).map(|d| d as u32);
+        }
+        self.digits(1, 2).map(|d| d as u32)
+    }
+
+    fn month_name(&mut self) -> Option<u32> {
+        let name = self.bytes.get(self.pos..self.pos + 3)?;
+        let month = MONTHS
+            .iter()
+            .position(|m| m.as_bytes().eq_ignore_ascii_case(name))?;
+        self.pos += 3;
+        Some(month as u32 + 1)
+    }
+
+    /// An optional fraction of a second after `.` or `,`, in milliseconds.
+    fn fraction(&mut self) -> u32 {
+        let start = self.pos;
+        if self.expect(b'.').or_else(|| self.expect(b',')).is_none() {
+            return 0;
+        }
+
+        let digits_start = self.pos;
+        self.skip_while(|c| c.is_ascii_digit());
+        let digits = &self.bytes[digits_start..self.pos];
+        if digits.is_empty() {
+            self.pos = start;
+            return 0;
+        }
+
+        digits
+            .iter()
+            .chain([b'0', b'0'].iter())
+            .take(3)
+            .fold(0, |millis, c| millis * 10 + (c - b'0') as u32)
+    }
+
+    /// An optional offset like `Z`, `+02:00`, `+0200` or ` -0700`, in minutes.
+    fn offset(&mut self) -> Option<i64> {
+        let start = self.pos;
+        if self.expect(b'Z').is_some() {
+            return Some(0);
+        }
+        if self.peek() == Some(b' ') {
+            self.pos += 1;
+        }
+
+        let sign = match self.peek() {
+            Some(b'+') => 1,
+            Some(b'-') => -1,
+            _ => {
+                self.pos = start;
+                return None;
+            }
+        };
+        self.pos += 1;
+
+        let parsed = (|| {
+            let hours = self.digits(2, 2)? as i64;
+            let _ = self.expect(b':');
+            let minutes = self.digits(2, 2)? as i64;
+            Some(sign * (hours * 60 + minutes))
+        })();
+        if parsed.is_none() {
+            self.pos = start;
+        }
+        parsed
+    }
+
+    /// `HH:MM:SS` with an optional fraction.
+    fn time(&mut self, dt: &mut DateTime) -> Option<()> {
+        dt.hour = self.digits(2, 2)? as u32;
+        self.expect(b':')?;
+        dt.minute = self.digits(2, 2)? as u32;
+        self.expect(b':')?;
+        dt.second = self.digits(2, 2)? as u32;
+        dt.millis = self.fraction();
+        Some(())
+    }
+}
+
+/// A window of time entered like `from 14:30 to 14:45`, both ends included.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct TimeRange {
+    pub from: Millis,
+    pub to: Millis,
+    /// The offset from UTC in minutes the range was entered in and is shown in.
+    pub offset: i64,
+}
+
+impl TimeRange {
+    /// Parses `[from] <time> to <time>`, with times as accepted by `parse_user_time`. An end
+    /// before the start is taken on the next day, as in `from 23:50 to 00:10`.
+    pub fn parse(
+        text: &str,
+        format: &TimestampFormat,
+        range: (Millis, Millis),
+        offset: i64,
+    ) -> Option<Self> {
+        let text = text.trim();
+        let text = match text.get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("from ") => &text[5..],
+            _ => text,
+        };
+
+        let separator = text.to_ascii_lowercase().find(" to ")?;
+        Self::between(
+            &text[..separator],
+            &text[separator + 4..],
+            format,
+            range,
+            offset,
+        )
+    }
+
+    /// The range between the times `from` and `to`, see `parse`.
+    pub fn between(
+        from: &str,
+        to: &str,
+        format: &TimestampFormat,
+        range: (Millis, Millis),
+        offset: i64,
+    ) -> Option<Self> {
+        let from = parse_user_time(from, format, range, offset)?;
+        let mut to = parse_user_time(to, format, range, offset)?;
+        if to < from {
+            to += DAY_MILLIS;
+        }
+        Some(Self { from, to, offset })
+    }
+}
+
+impl std::fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "from {} to {}",
+            format_millis(self.from, self.offset),
+            format_millis(self.to, self.offset)
+        )
+    }
+}
+
+/// How the timestamps of a file are written.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TimestampFormat {
+    /// ISO-8601 and RFC 3339, e.g. `2023-10-11T22:14:15.003Z` or `2023-10-11 22:14:15,003`.
+    Iso8601,
+    /// BSD syslog, e.g. `Oct 11 22:14:15`, which has no year. RFC 5424 syslog uses ISO-8601.
+    Syslog { year: i64 },
+    /// Apache/nginx access logs, e.g. `[10/Oct/2000:13:55:36 -0700]`.
+    Apache,
+    /// 13 digit milliseconds since the epoch, e.g. `1697062455003`.
+    EpochMillis,
+    /// strftime-like format from the settings, see `parse_custom`.
+    Custom(String),
+}
+
+impl TimestampFormat {
+    /// Formats to try, in this order. A custom format from the settings comes first.
+    fn candidates(custom: Option<&str>) -> Vec<TimestampFormat> {
+        custom
+            .map(|format| TimestampFormat::Custom(format.to_owned()))
+            .into_iter()
+            .chain([
+                TimestampFormat::Iso8601,
+                TimestampFormat::Apache,
+                TimestampFormat::Syslog {
+                    year: current_year(),
+                },
+                TimestampFormat::EpochMillis,
+            ])
+            .collect()
+    }
+
+    /// The format parsing most of `lines`, if any parses at least one.
+    pub fn detect<S: AsRef<str>>(lines: &[S], custom: Option<&str>) -> Option<TimestampFormat> {
+        let mut best: Option<(TimestampFormat, usize)> = None;
+
+        for format in Self::candidates(custom) {
+            let parsed = lines
+                .iter()
+                .filter(|line| format.parse(line.as_ref()).is_some())
+                .count();
+            if parsed > best.as_ref().map_or(0, |(_, count)| *count) {
+                best = Some((format, parsed));
+            }
+        }
+
+        best.map(|(format, _)| format)
+    }
+
+    /// `detect` over the first lines of the file at `path`.
+    pub fn detect_in_file(path: &str, custom: Option<&str>) -> anyhow::Result<Option<Self>> {
+        let mut reader = BufReader::new(open_log(path)?);
+        let mut first_lines = Vec::new();
+        let mut buf = Vec::new();
+        while first_lines.len() < DETECT_LINES && reader.read_until(b'\n', &mut buf)? > 0 {
+            first_lines.push(String::from_utf8_lossy(&buf).into_owned());
+            buf.clear();
+        }
+
+        Ok(Self::detect(&first_lines, custom))
+    }
+
+    /// The timestamp at the start of `line`. Leading whitespace and `[` are skipped; for
+    /// Apache logs the timestamp is the first bracketed part.
+    pub fn parse(&self, line: &str) -> Option<Millis> {
+        self.parse_with_offset(line).map(|(millis, _)| millis)
+    }
+
+    /// `parse`, together with the offset from UTC in minutes the timestamp was written with,
+    /// if it has one. Epoch times are UTC.
+    pub fn parse_with_offset(&self, line: &str) -> Option<(Millis, Option<i64>)> {
+        let mut cursor = Cursor::new(line);
+        if *self != TimestampFormat::Apache {
+            cursor.skip_while(|c| c == b' ' || c == b'\t' || c == b'[');
+        }
+
+        match self {
+            TimestampFormat::Iso8601 => Self::parse_iso8601(&mut cursor),
+            TimestampFormat::Syslog { year } => Self::parse_syslog(&mut cursor, *year),
+            TimestampFormat::Apache => Self::parse_apache(&mut cursor),
+            TimestampFormat::EpochMillis => {
+                let millis = cursor.digits(13, 13)?;
+                match cursor.peek() {
+                    Some(c) if c.is_ascii_digit() => None,
+                    _ => Some((millis as Millis, Some(0))),
+                }
+            }
+            TimestampFormat::Custom(format) => Self::parse_custom(&mut cursor, format),
+        }
+    }
+
+    fn parse_iso8601(cursor: &mut Cursor) -> Option<(Millis, Option<i64>)> {
+        let mut dt = DateTime::new(cursor.digits(4, 4)? as i64);
+        cursor.expect(b'-')?;
+        dt.month = cursor.digits(2, 2)? as u32;
+        cursor.expect(b'-')?;
+        dt.day = cursor.digits(2, 2)? as u32;
+        cursor.expect(b'T').or_else(|| cursor.expect(b' '))?;
+        cursor.time(&mut dt)?;
+        dt.offset = cursor.offset();
+        dt.to_parsed()
+    }
+
+    fn parse_syslog(cursor: &mut Cursor, year: i64) -> Option<(Millis, Option<i64>)> {
+        // RFC 5424: `<34>1 2003-10-11T22:14:15.003Z`
+        if cursor.expect(b'<').is_some() {
+            cursor.digits(1, 3)?;
+            cursor.expect(b'>')?;
+            let before_version = cursor.pos;
+            if cursor.digits(1, 2).is_some() && cursor.expect(b' ').is_some() {
+                return Self::parse_iso8601(cursor);
+            }
+            cursor.pos = before_version;
+        }
+
+        let mut dt = DateTime::new(year);
+        dt.month = cursor.month_name()?;
+        cursor.expect(b' ')?;
+        dt.day = cursor.day()?;
+        cursor.expect(b' ')?;
+        cursor.time(&mut dt)?;
+        dt.to_parsed()
+    }
+
+    fn parse_apache(cursor: &mut Cursor) -> Option<(Millis, Option<i64>)> {
+        let open = cursor.bytes.iter().take(128).position(|c| *c == b'[')?;
+        cursor.pos = open + 1;
+
+        let day = cursor.digits(2, 2)? as u32;
+        cursor.expect(b'/')?;
+        let month = cursor.month_name()?;
+        cursor.expect(b'/')?;
+        let mut dt = DateTime::new(cursor.digits(4, 4)? as i64);
+        dt.month = month;
+        dt.day = day;
+        cursor.expect(b':')?;
+        cursor.time(&mut dt)?;
+        dt.offset = cursor.offset();
+        cursor.expect(b']')?;
+        dt.to_parsed()
+    }
+
+    /// Parses `format`, which may contain `%Y` (4 digit year), `%y` (2 digit year, 20xx),
+    /// `%m`, `%d`, `%e` (space padded day), `%b` (month name), `%H`, `%M`, `%S`, `%f`
+    /// (fraction after the seconds, including its `.` or `,`), `%z` (offset), `%s` (epoch
+    /// seconds) and `%%`. Other characters have to match exactly.
+    fn parse_custom(cursor: &mut Cursor, format: &str) -> Option<(Millis, Option<i64>)> {
+        let mut dt = DateTime::new(current_year());
+        let mut epoch_seconds = None;
+        let mut spec = format.bytes();
+
+        while let Some(c) = spec.next() {
+            if c != b'%' {
+                cursor.expect(c)?;
+                continue;
+            }
+
+            match spec.next()? {
+                b'Y' => dt.year = cursor.digits(4, 4)? as i64,
+                b'y' => dt.year = 2000 + cursor.digits(2, 2)? as i64,
+                b'm' => dt.month = cursor.digits(1, 2)? as u32,
+                b'd' => dt.day = cursor.digits(1, 2)? as u32,
+                b'e' => dt.day = cursor.day()?,
+                b'b' => dt.month = cursor.month_name()?,
+                b'H' => dt.hour = cursor.digits(1, 2)? as u32,
+                b'M' => dt.minute = cursor.digits(2, 2)? as u32,
+                b'S' => dt.second = cursor.digits(2, 2)? as u32,
+                b'f' => dt.millis = cursor.fraction(),
+                b'z' => dt.offset = cursor.offset(),
+                b's' => epoch_seconds = Some(cursor.digits(1, 12)? as Millis),
+                b'%' => cursor.expect(b'%')?,
+                _ => return None,
+            }
+        }
+
+        match epoch_seconds {
+            Some(seconds) => Some((seconds * 1000 + dt.millis as Millis, Some(0))),
+            None => dt.to_parsed(),
+        }
+    }
+}
+
+/// Timestamps of one page of lines, see `TimestampIndex`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct PageTimes {
+    /// The first line of the page with a timestamp (0-based), and that timestamp.
+    pub first: (u64, Millis),
+    pub min: Millis,
+    pub max: Millis,
+}
+
+/// Sparse line → timestamp index with one entry per page of the `LineChunk` table of a
+/// `LineBasedFileView`, i.e. per `lines_per_page` lines.
+pub(crate) struct TimestampIndex {
+    pub format: TimestampFormat,
+    pub lines_per_page: u64,
+    /// `None` for pages without any timestamp.
+    pub pages: Vec<Option<PageTimes>>,
+    /// The offset from UTC in minutes of the first timestamp, 0 if it has none. Times are
+    /// shown to and entered by the user at this offset, so they read as in the file.
+    pub offset: i64,
+}
+
+impl TimestampIndex {
+    /// Detects the timestamp format of the file at `path` and reads the timestamps of all
+    /// lines. Returns `None` if the file has no timestamps in a known format.
+    pub fn build(
+        path: &str,
+        lines_per_page: u64,
+        custom: Option<&str>,
+    ) -> anyhow::Result<Option<Self>> {
+        let format = match TimestampFormat::detect_in_file(path, custom)? {
+            Some(format) => format,
+            None => return Ok(None),
+        };
+
+        let mut reader = BufReader::new(open_log(path)?);
+        let mut pages: Vec<Option<PageTimes>> = vec![];
+        let mut buf = Vec::new();
+        let mut line = 0u64;
+        let mut offset = None;
+
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+
+            if line % lines_per_page == 0 {
+                pages.push(None);
+            }
+
+            if let Some((millis, written_offset)) =
+                format.parse_with_offset(&String::from_utf8_lossy(&buf))
+            {
+                offset = offset.or(Some(written_offset.unwrap_or(0)));
+                if let Some(page) = pages.last_mut() {
+                    match page {
+                        Some(times) => {
+                            times.min = times.min.min(millis);
+                            times.max = times.max.max(millis);
+                        }
+                        None => {
+                            *page = Some(PageTimes {
+                                first: (line, millis),
+                                min: millis,
+                                max: millis,
+                            })
+                        }
+                    }
+                }
+            }
+            line += 1;
+        }
+
+        Ok(Some(Self {
+            format,
+            lines_per_page,
+            pages,
+            offset: offset.unwrap_or(0),
+        }))
+    }
+
+    /// The first line (0-based) with a timestamp at or after `target`, reading the text of
+    /// lines with `line_text`, which returns `None` past the end of the file.
+    ///
+    /// Binary searches the pages by the first timestamp on each, skipping pages without any.
+    /// As timestamps may be out of order, lines before the page found may already be at or
+    /// after `target`, so reading starts at the page before it. Pages whose latest timestamp
+    /// is before `target` aren't read at all.
+    pub fn find_line(
+        &self,
+        target: Millis,
+        mut line_text: impl FnMut(u64) -> Option<String>,
+    ) -> Option<u64> {
+        let timed: Vec<(u64, PageTimes)> = self
+            .pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page, times)| times.map(|times| (page as u64, times)))
+            .collect();
+        let found = timed.partition_point(|(_, times)| times.first.1 < target);
+
+        for (page, times) in &timed[found.saturating_sub(1)..] {
+            if times.max < target {
+                continue;
+            }
+
+            let page_end = (page + 1) * self.lines_per_page;
+            for line in times.first.0..page_end {
+                let text = line_text(line)?;
+                if self
+                    .format
+                    .parse(&text)
+                    .is_some_and(|millis| millis >= target)
+                {
+                    return Some(line);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// `find_line` over the lines of `view`.
+    pub fn line_at_or_after<R: Read + Seek>(
+        &self,
+        target: Millis,
+        view: &mut LineBasedFileView<R>,
+    ) -> Option<u64> {
+        let line_count = view.line_count();
+        self.find_line(target, |line| {
+            (line < line_count).then(|| view.get_line(line).ok())?
+        })
+    }
+
+    /// The 0-based lines of `view` within `range`: from the first line at or after its start
+    /// up to the first line after its end. Lines without a timestamp, like those of a stack
+    /// trace, belong to the timestamped line before them.
+    pub fn lines_in<R: Read + Seek>(
+        &self,
+        range: &TimeRange,
+        view: &mut LineBasedFileView<R>,
+    ) -> Range<u64> {
+        let line_count = view.line_count();
+        let start = self
+            .line_at_or_after(range.from, view)
+            .unwrap_or(line_count);
+        let end = self
+            .line_at_or_after(range.to + 1, view)
+            .unwrap_or(line_count);
+        start..end.max(start)
+    }
+
+    /// The first and last timestamp of the file.
+    pub fn range(&self) -> Option<(Millis, Millis)> {
+        let min = self.pages.iter().flatten().map(|p| p.min).min()?;
+        let max = self.pages.iter().flatten().map(|p| p.max).max()?;
+        Some((min, max))
+    }
+
+    /// The first lines of `buckets` spans of equal time between the first and the last
+    /// timestamp of the file, for a `MatchHistogram` over time. Only the first timestamp of
+    /// each page is known, so a bucket starts at the first page starting at or after its time
+    /// and buckets within one page stay empty.
+    pub fn bucket_starts(&self, buckets: usize, line_count: u64) -> Vec<u64> {
+        let (first, last) = match self.range() {
+            Some(range) => range,
+            None => return vec![0],
+        };
+        let timed: Vec<(u64, Millis)> = self.pages.iter().flatten().map(|p| p.first).collect();
+        let buckets = buckets.max(1) as i128;
+        let span = (last - first) as i128;
+
+        let mut start = 0;
+        (0..buckets)
+            .map(|bucket| {
+                let time = first + (span * bucket / buckets) as Millis;
+                let page = timed.partition_point(|(_, first)| *first < time);
+                // out of order timestamps must not move a bucket before the previous one
+                if bucket > 0 {
+                    start = timed
+                        .get(page)
+                        .map_or(line_count, |(line, _)| *line)
+                        .max(start);
+                }
+                start
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for TimestampIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimestampIndex")
+            .field("format", &self.format)
+            .field("pages", &self.pages.len())
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(date: (i64, u32, u32), time: (i64, i64, i64), millis: i64) -> Millis {
+        let (hour, minute, second) = time;
+        days_from_civil(date.0, date.1, date.2) * DAY_MILLIS
+            + (hour * 3600 + minute * 60 + second) * 1000
+            + millis
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        for days in (-800_000..800_000).step_by(97) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn iso8601() {
+        let format = TimestampFormat::Iso8601;
+        let expected = utc((2023, 10, 11), (22, 14, 15), 3);
+        assert_eq!(format.parse("2023-10-11T22:14:15.003Z"), Some(expected));
+        assert_eq!(format.parse("2023-10-11 22:14:15,003 INFO"), Some(expected));
+        assert_eq!(format.parse("[2023-10-11T22:14:15.003] x"), Some(expected));
+        assert_eq!(
+            format.parse_with_offset("2023-10-11T22:14:15.003"),
+            Some((expected, None))
+        );
+        assert_eq!(format.parse("2023-10-11T22:14"), None);
+        assert_eq!(format.parse("2023-13-11T22:14:15"), None);
+    }
+
+    #[test]
+    fn iso8601_offsets() {
+        let format = TimestampFormat::Iso8601;
+        let expected = utc((2023, 10, 11), (20, 14, 15), 0);
+        assert_eq!(
+            format.parse_with_offset("2023-10-11T22:14:15+02:00"),
+            Some((expected, Some(120)))
+        );
+        assert_eq!(
+            format.parse_with_offset("2023-10-11T22:14:15+0200"),
+            Some((expected, Some(120)))
+        );
+        assert_eq!(
+            format.parse_with_offset("2023-10-11T13:14:15-07:00"),
+            Some((expected, Some(-420)))
+        );
+        assert_eq!(
+            format.parse_with_offset("2023-10-11T20:14:15Z"),
+            Some((expected, Some(0)))
+        );
+        // not an offset, the text after the timestamp is left alone
+        assert_eq!(
+            format.parse_with_offset("2023-10-11T20:14:15 +x"),
+            Some((expected, None))
+        );
+    }
+
+    #[test]
+    fn syslog() {
+        let format = TimestampFormat::Syslog { year: 2023 };
+        assert_eq!(
+            format.parse("Oct 11 22:14:15 host app[1]: x"),
+            Some(utc((2023, 10, 11), (22, 14, 15), 0))
+        );
+        assert_eq!(
+            format.parse("Oct  1 02:04:05 host"),
+            Some(utc((2023, 10, 1), (2, 4, 5), 0))
+        );
+        assert_eq!(
+            format.parse_with_offset("<34>1 2003-10-11T22:14:15.003Z host"),
+            Some((utc((2003, 10, 11), (22, 14, 15), 3), Some(0)))
+        );
+        assert_eq!(format.parse("Foo 11 22:14:15"), None);
+    }
+
+    #[test]
+    fn apache() {
+        let format = TimestampFormat::Apache;
+        assert_eq!(
+            format.parse_with_offset(
+                r#"127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "GET / HTTP/1.0" 200"#
+            ),
+            Some((utc((2000, 10, 10), (20, 55, 36), 0), Some(-420)))
+        );
+        assert_eq!(
+            format.parse("127.0.0.1 - - [10/Oct/2000:13:55:36 -0700"),
+            None
+        );
+        assert_eq!(format.parse("no timestamp here"), None);
+    }
+
+    #[test]
+    fn epoch_millis() {
+        let format = TimestampFormat::EpochMillis;
+        assert_eq!(
+            format.parse_with_offset("1697062455003 x"),
+            Some((1_697_062_455_003, Some(0)))
+        );
+        assert_eq!(format.parse("16970624550031"), None);
+        assert_eq!(format.parse("169706245500"), None);
+    }
+
+    #[test]
+    fn custom() {
+        let format = TimestampFormat::Custom("%d.%m.%Y %H:%M:%S%f".to_owned());
+        assert_eq!(
+            format.parse("11.10.2023 22:14:15.250 x"),
+            Some(utc((2023, 10, 11), (22, 14, 15), 250))
+        );
+
+        let format = TimestampFormat::Custom("%y%m%d %H%M%S %z".to_owned());
+        assert_eq!(
+            format.parse_with_offset("231011 221415 +0100"),
+            Some((utc((2023, 10, 11), (21, 14, 15), 0), Some(60)))
+        );
+
+        let format = TimestampFormat::Custom("%s%f".to_owned());
+        assert_eq!(
+            format.parse_with_offset("1697062455.5"),
+            Some((1_697_062_455_500, Some(0)))
+        );
+
+        let format = TimestampFormat::Custom("%b %e %H:%M:%S %%".to_owned());
+        assert_eq!(
+            format.parse("Oct  1 02:04:05 %"),
+            Some(utc((current_year(), 10, 1), (2, 4, 5), 0))
+        );
+    }
+
+    #[test]
+    fn detect() {
+        let lines = [
+            "Oct 11 22:14:15 host a",
+            "  continued",
+            "Oct 11 22:14:16 host b",
+        ];
+        assert_eq!(
+            TimestampFormat::detect(&lines, None),
+            Some(TimestampFormat::Syslog {
+                year: current_year()
+            })
+        );
+        assert_eq!(
+            TimestampFormat::detect(&["2023-10-11 22:14:15 a"], Some("%H:%M")),
+            Some(TimestampFormat::Iso8601)
+        );
+        assert_eq!(TimestampFormat::detect(&["plain text"], None), None);
+    }
+
+    #[test]
+    fn format_with_offset() {
+        let millis = utc((2023, 10, 11), (21, 32, 10), 250);
+        assert_eq!(format_millis(millis, 0), "2023-10-11 21:32:10.250");
+        assert_eq!(format_millis(millis, -420), "2023-10-11 14:32:10.250");
+        assert_eq!(format_millis(millis, 180), "2023-10-12 00:32:10.250");
+    }
+
+    #[test]
+    fn user_time_in_file_offset() {
+        let format = TimestampFormat::Iso8601;
+        // a file written at -07:00 from 13:00 to 17:00 local time
+        let range = (
+            utc((2023, 10, 11), (20, 0, 0), 0),
+            utc((2023, 10, 12), (0, 0, 0), 0),
+        );
+        let expected = utc((2023, 10, 11), (21, 32, 10), 0);
+
+        assert_eq!(
+            parse_user_time("14:32:10", &format, range, -420),
+            Some(expected)
+        );
+        assert_eq!(
+            parse_user_time("2023-10-11 14:32:10", &format, range, -420),
+            Some(expected)
+        );
+        // times with an offset of their own keep it
+        assert_eq!(
+            parse_user_time("2023-10-11T21:32:10Z", &format, range, -420),
+            Some(expected)
+        );
+        // the same wall-clock time in a file written at +02:00
+        assert_eq!(
+            parse_user_time("23:32:10", &format, range, 120),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn user_time_of_day() {
+        let format = TimestampFormat::Iso8601;
+        let range = (
+            utc((2023, 10, 11), (22, 0, 0), 0),
+            utc((2023, 10, 12), (2, 0, 0), 0),
+        );
+        assert_eq!(
+            parse_user_time("23:15", &format, range, 0),
+            Some(utc((2023, 10, 11), (23, 15, 0), 0))
+        );
+        // after midnight, on the second day of the range
+        assert_eq!(
+            parse_user_time("1:05:00.5", &format, range, 0),
+            Some(utc((2023, 10, 12), (1, 5, 0), 500))
+        );
+        // outside of the range, on its first day
+        assert_eq!(
+            parse_user_time("12:00", &format, range, 0),
+            Some(utc((2023, 10, 11), (12, 0, 0), 0))
+        );
+        assert_eq!(parse_user_time("12", &format, range, 0), None);
+        assert_eq!(parse_user_time("12:00 x", &format, range, 0), None);
+    }
+
+    #[test]
+    fn time_range() {
+        let format = TimestampFormat::Apache;
+        let range = (
+            utc((2023, 10, 11), (20, 0, 0), 0),
+            utc((2023, 10, 12), (8, 0, 0), 0),
+        );
+
+        let parsed = TimeRange::parse("from 23:50 TO 00:10", &format, range, 120);
+        let expected = TimeRange {
+            from: utc((2023, 10, 11), (21, 50, 0), 0),
+            to: utc((2023, 10, 11), (22, 10, 0), 0),
+            offset: 120,
+        };
+        assert_eq!(parsed, Some(expected));
+        assert_eq!(
+            expected.to_string(),
+            "from 2023-10-11 23:50:00.000 to 2023-10-12 00:10:00.000"
+        );
+        // what is shown parses to the same range again
+        assert_eq!(
+            TimeRange::parse(&expected.to_string(), &format, range, 120),
+            Some(expected)
+        );
+        assert_eq!(TimeRange::parse("23:50", &format, range, 120), None);
+    }
+}
@@ -6,33 +6,126 @@ use std::os::windows::prelude::OsStrExt;
 use std::ptr;
 use windows::Win32::Foundation::BOOL;
 use windows::Win32::Graphics::Dwm::DWMWA_USE_IMMERSIVE_DARK_MODE;
+use windows::Win32::System::DataExchange::RegisterClipboardFormatW;
 use winsafe::co::{CF, GMEM};
-use winsafe::prelude::{kernel_Hglobal, user_Hwnd, Handle};
-use winsafe::{EmptyClipboard, SetClipboardData, HGLOBAL, HWND};
+use winsafe::prelude::{kernel_Hglobal, shell_IFileDialog, shell_IModalWindow, shell_IShellItem, user_Hwnd, Handle};
+use winsafe::{co, EmptyClipboard, IFileSaveDialog, SetClipboardData, HGLOBAL, HWND};
+
+pub(crate) const TEXT_FILE_TYPES: &[(&str, &str)] = &[("Text files", "*.txt;*.log"), ("All files", "*.*")];
 
 pub(crate) fn copy_text_to_clipboard(hwnd: &HWND, text: &str) -> anyhow::Result<()> {
     let _open = hwnd.OpenClipboard()?;
     EmptyClipboard()?;
 
+    set_unicode_clipboard_data(text)
+}
+
+/// Puts `text` on the clipboard together with its HTML and RTF renderings, so that the target
+/// application can pick the richest format it understands.
+pub(crate) fn copy_rich_text_to_clipboard(
+    hwnd: &HWND,
+    text: &str,
+    html: &str,
+    rtf: &str,
+) -> anyhow::Result<()> {
+    let _open = hwnd.OpenClipboard()?;
+    EmptyClipboard()?;
+
+    set_unicode_clipboard_data(text)?;
+
+    let html_format = unsafe { RegisterClipboardFormatW(windows::core::w!("HTML Format")) };
+    if html_format != 0 {
+        set_clipboard_bytes(unsafe { CF::from_raw(html_format) }, html_clipboard_fragment(html).as_bytes())?;
+    }
+
+    let rtf_format = unsafe { RegisterClipboardFormatW(windows::core::w!("Rich Text Format")) };
+    if rtf_format != 0 {
+        set_clipboard_bytes(unsafe { CF::from_raw(rtf_format) }, rtf.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn set_unicode_clipboard_data(text: &str) -> anyhow::Result<()> {
     let mut wstr = text.encode_utf16().collect::<Vec<u16>>();
     wstr.push(0); // terminate with \0
 
-    let hg = HGLOBAL::GlobalAlloc(
-        Some(GMEM::MOVEABLE),
-        wstr.len() * std::mem::size_of::<u16>(),
-    )?;
+    let bytes = unsafe {
+        std::slice::from_raw_parts(wstr.as_ptr() as *const u8, wstr.len() * std::mem::size_of::<u16>())
+    };
+    set_clipboard_bytes(CF::UNICODETEXT, bytes)
+}
+
+/// The clipboard has to be open already.
+fn set_clipboard_bytes(format: CF, bytes: &[u8]) -> anyhow::Result<()> {
+    let hg = HGLOBAL::GlobalAlloc(Some(GMEM::MOVEABLE), bytes.len() + 1)?;
     {
         let dst = hg.GlobalLock()?;
-        unsafe { ptr::copy_nonoverlapping(wstr.as_ptr(), dst.as_ptr() as _, wstr.len()) };
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), dst.as_ptr() as *mut u8, bytes.len());
+            // text formats other than CF_UNICODETEXT want a terminating \0 as well
+            *(dst.as_ptr() as *mut u8).add(bytes.len()) = 0;
+        }
     }
 
     unsafe {
-        let _ = SetClipboardData(CF::UNICODETEXT, hg.ptr() as _)?;
+        let _ = SetClipboardData(format, hg.ptr() as _)?;
     }
 
     Ok(())
 }
 
+/// Wraps an HTML fragment into the header of the "HTML Format" clipboard format, which holds
+/// the byte offsets of the document and of the fragment.
+fn html_clipboard_fragment(html: &str) -> String {
+    let header = |start_html: usize, end_html: usize, start_fragment: usize, end_fragment: usize| {
+        format!(
+            "Version:0.9\r\nStartHTML:{start_html:010}\r\nEndHTML:{end_html:010}\r\nStartFragment:{start_fragment:010}\r\nEndFragment:{end_fragment:010}\r\n"
+        )
+    };
+
+    let prefix = "<html><body><!--StartFragment-->";
+    let suffix = "<!--EndFragment--></body></html>";
+
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + prefix.len();
+    let end_fragment = start_fragment + html.len();
+    let end_html = end_fragment + suffix.len();
+
+    format!(
+        "{}{prefix}{html}{suffix}",
+        header(start_html, end_html, start_fragment, end_fragment)
+    )
+}
+
+/// Asks for the file to save to. Returns `None` if the user cancelled.
+pub(crate) fn ask_save_path(
+    hwnd: &HWND,
+    file_name: &str,
+    file_types: &[(&str, &str)],
+) -> anyhow::Result<Option<String>> {
+    let _com = winsafe::CoInitializeEx(co::COINIT::APARTMENTTHREADED | co::COINIT::DISABLE_OLE1DDE)?;
+
+    let file_save = winsafe::CoCreateInstance::<IFileSaveDialog>(
+        &co::CLSID::FileSaveDialog,
+        None,
+        co::CLSCTX::INPROC_SERVER,
+    )?;
+
+    file_save.SetFileTypes(file_types)?;
+    file_save.SetFileTypeIndex(1)?;
+    if let Some((_, extension)) = file_name.rsplit_once('.') {
+        file_save.SetDefaultExtension(extension)?;
+    }
+    file_save.SetFileName(file_name)?;
+
+    if file_save.Show(hwnd)? {
+        Ok(Some(file_save.GetResult()?.GetDisplayName(co::SIGDN::FILESYSPATH)?))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn encode_wide(string: impl AsRef<std::ffi::OsStr>) -> Vec<u16> {
     string.as_ref().encode_wide().chain(once(0)).collect()
 }